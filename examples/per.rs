@@ -0,0 +1,120 @@
+// Measure the packet error rate, the false positive rate and the decode latency of the
+// receiver for simulated acoustic channels. The results are written as CSV to stdout.
+//
+// Usage: cargo run --release --features test-utils --example per -- [PACKETS]
+
+use std::collections::HashMap;
+use std::env;
+use std::thread;
+
+use audio_barcode::test_utils::channel::Channel;
+use audio_barcode::test_utils::*;
+use audio_barcode::*;
+
+const SAMPLE_RATE: u32 = 44100;
+const DEFAULT_PACKETS: usize = 100;
+// SNR of white noise over the full band, the bandwidth of a symbol is about 11 Hz
+const SNRS: [f32; 6] = [-30., -25., -20., -15., -10., 0.];
+const REVERB_RT60S: [f32; 3] = [0., 0.3, 0.6];
+const REVERB_WET: f32 = 0.3;
+const DRIFT_PPMS: [i32; 3] = [0, 100, 500];
+// Silence before each packet
+const GAP_TIME: f32 = 0.5;
+
+struct Results {
+    received: usize,
+    false_positives: usize,
+    duration: f32,
+    latencies: Vec<f32>,
+}
+
+fn measure(packets: usize, snr: f32, rt60: f32, ppm: i32, seed: u64) -> Results {
+    let mut channel = Channel::new(SAMPLE_RATE, seed).drift(ppm).white_noise(snr);
+    if rt60 > 0. {
+        channel = channel.reverb(rt60, REVERB_WET);
+    }
+    let mut transceiver = Transceiver::new(SAMPLE_RATE);
+    let gap = vec![0.; ((SAMPLE_RATE as f32) * GAP_TIME) as usize];
+    let mut expected = HashMap::new();
+    let mut results = Results {
+        received: 0,
+        false_positives: 0,
+        duration: 0.,
+        latencies: Vec::new(),
+    };
+    let mut sample_count = 0;
+    for i in 0..=packets {
+        let mut samples = gap.clone();
+        // Flush the receiver with silence after the last packet
+        if i < packets {
+            let payload = rand_payload((seed as usize) * packets + i);
+            for &frequency in transceiver.send(&payload).iter() {
+                samples.extend(transceiver.generate_beep(frequency));
+            }
+            let end = sample_count + samples.len() * (1_000_000 + ppm) as usize / 1_000_000;
+            expected.insert(payload, end);
+        }
+        // Each packet is processed separately, the reverb tail is appended to the output
+        let samples = channel.process(&samples);
+        for &sample in samples.iter() {
+            sample_count += 1;
+            if let Some(payload) = transceiver.push_sample(sample) {
+                match expected.remove(&payload) {
+                    // The drift estimate of the end can be late by a few samples
+                    Some(end) => {
+                        results.received += 1;
+                        let latency = sample_count.saturating_sub(end);
+                        results
+                            .latencies
+                            .push((latency as f32) / (SAMPLE_RATE as f32));
+                    }
+                    None => results.false_positives += 1,
+                }
+            }
+        }
+    }
+    results.duration = (sample_count as f32) / (SAMPLE_RATE as f32);
+    results
+}
+
+fn main() {
+    let packets = match env::args().nth(1) {
+        Some(arg) => (arg.parse().ok())
+            .filter(|&packets| packets > 0)
+            .expect("PACKETS must be a positive number"),
+        None => DEFAULT_PACKETS,
+    };
+    println!(
+        "snr_db,reverb_rt60,drift_ppm,packets,received,packet_error_rate,\
+         false_positives,false_positives_per_hour,mean_latency,max_latency"
+    );
+    let mut threads = Vec::new();
+    for &snr in SNRS.iter() {
+        for &rt60 in REVERB_RT60S.iter() {
+            for &ppm in DRIFT_PPMS.iter() {
+                let seed = threads.len() as u64;
+                let thread = thread::spawn(move || measure(packets, snr, rt60, ppm, seed));
+                threads.push((snr, rt60, ppm, thread));
+            }
+        }
+    }
+    for (snr, rt60, ppm, thread) in threads {
+        let results = thread.join().unwrap();
+        let latencies = &results.latencies;
+        let mean_latency = latencies.iter().sum::<f32>() / (latencies.len() as f32);
+        let max_latency = latencies.iter().cloned().fold(f32::NAN, f32::max);
+        println!(
+            "{},{},{},{},{},{},{},{},{},{}",
+            snr,
+            rt60,
+            ppm,
+            packets,
+            results.received,
+            1. - (results.received as f32) / (packets as f32),
+            results.false_positives,
+            (results.false_positives as f32) * 3600. / results.duration,
+            mean_latency,
+            max_latency
+        );
+    }
+}
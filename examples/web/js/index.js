@@ -8,8 +8,6 @@ const PERF_MIN = 0.98;
 const BEEP_TIME = wasm.MessageTransceiver.get_beep_time();
 const ATTACK_TIME = wasm.MessageTransceiver.get_attack_time();
 const RELEASE_TIME = wasm.MessageTransceiver.get_release_time();
-const PAYLOAD_LEN = wasm.MessageTransceiver.get_payload_len();
-const SYMBOL_MNEMONICS = wasm.MessageTransceiver.get_symbol_mnemonics();
 const MAX_MESSAGE_LEN = wasm.MessageTransceiver.get_max_message_len();
 
 let message_btn = document.querySelector("#message-btn");
@@ -44,11 +42,7 @@ function log(msg, classes) {
 }
 
 function log_packet(payload, direction) {
-    let msg = "";
-    for (let i = 0; i < payload.length; i++) {
-        msg += SYMBOL_MNEMONICS.charAt(payload[i]);
-    }
-    log(msg, ["packet", direction]);
+    log(wasm.MessageTransceiver.format_code(new Uint8Array(payload)), ["packet", direction]);
 }
 
 function log_message(text, direction) {
@@ -61,11 +55,39 @@ function on_received(payload) {
     log_packet(payload, "rx");
 }
 
+let message_status = null;
+
 function on_received_message(text) {
+    if (message_status) {
+        message_status.remove();
+        message_status = null;
+    }
     let data = (new TextDecoder()).decode(new Uint8Array(text));
     log_message(data, "rx");
 }
 
+function on_message_status(status, received, total) {
+    if (status === "started" && message_status) {
+        message_status.remove();
+        message_status = null;
+    }
+    if (!message_status) {
+        message_status = document.createElement("p");
+        message_status.classList.add("rx");
+        log_container.prepend(message_status);
+    }
+    if (status === "started") {
+        message_status.textContent = "Receiving message…";
+    } else if (status === "progress") {
+        message_status.textContent = "Receiving message: " + received + " of " + total + " bytes";
+    } else {
+        message_status.textContent = status === "timeout" ?
+            "Message timed out" : "Dropped message with invalid padding";
+        message_status.classList.add("err");
+        message_status = null;
+    }
+}
+
 let pending_transmissions = [];
 let transmission_in_progress = false;
 
@@ -113,6 +135,7 @@ function on_transmit(payload, frequencies) {
 }
 
 let transceiver = wasm.MessageTransceiver.new(audioCtx.sampleRate, on_received, on_received_message, on_transmit);
+transceiver.set_on_message_status(on_message_status);
 
 function on_gum_err(err) {
     console.log("The following gUM error occured: " + err);
@@ -185,26 +208,16 @@ message_input.addEventListener("input", validate_message_input);
 
 packet_form.addEventListener("submit", function(event) {
     event.preventDefault();
-    let text = packet_input.value;
-    let payload = new Uint8Array(PAYLOAD_LEN);
-    for (let i = 0, j = 0; i < text.length && j < payload.length; i++) {
-        let symbol = SYMBOL_MNEMONICS.indexOf(text[i]);
-        if (symbol !== -1) {
-            payload[j] = symbol;
-            j++;
-        }
-    }
+    let payload = wasm.MessageTransceiver.parse_code(packet_input.value);
     transceiver.send(payload);
 });
 
-packet_input.setAttribute("maxlength", PAYLOAD_LEN);
 function validate_packet_input() {
-    let text = packet_input.value;
-    for (let symbol of text) {
-        if (SYMBOL_MNEMONICS.indexOf(symbol) == -1) {
-            packet_input.setCustomValidity("Allowed symbols: " + SYMBOL_MNEMONICS);
-            return;
-        }
+    try {
+        wasm.MessageTransceiver.parse_code(packet_input.value);
+    } catch (err) {
+        packet_input.setCustomValidity("Invalid code: " + err);
+        return;
     }
     packet_input.setCustomValidity("");
 }
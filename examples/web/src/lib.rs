@@ -1,15 +1,15 @@
 extern crate audio_barcode;
 extern crate js_sys;
 
+use audio_barcode::message::{
+    MessageDecoder, MessageEncoder, MessageEvent, DEFAULT_MAX_MESSAGE_LEN,
+};
 use audio_barcode::{
-    Transceiver, ATTACK_TIME, BEEP_TIME, PACKET_LEN, PAYLOAD_LEN, RELEASE_TIME, SYMBOL_BITS,
-    SYMBOL_COUNT, SYMBOL_MNEMONICS,
+    format_code, parse_code, Transceiver, ATTACK_TIME, BEEP_TIME, PACKET_LEN, PAYLOAD_LEN,
+    RELEASE_TIME,
 };
 use wasm_bindgen::prelude::*;
 
-pub const MAX_MESSAGE_LEN: usize = 255;
-const MAX_TIME_BETWEEN_PACKETS: f32 = BEEP_TIME * (PACKET_LEN as f32);
-
 // When the `wee_alloc` feature is enabled, this uses `wee_alloc` as the global
 // allocator.
 #[cfg(feature = "wee_alloc")]
@@ -37,10 +37,11 @@ pub struct MessageTransceiver {
     on_transmit: Box<dyn FnMut([u8; PAYLOAD_LEN], [f32; PACKET_LEN])>,
     on_received: Box<dyn FnMut([u8; PAYLOAD_LEN])>,
     on_received_message: Box<dyn FnMut(Box<[u8]>)>,
+    on_message_status: Option<Box<dyn FnMut(&str, usize, usize)>>,
+    message_encoder: MessageEncoder,
+    message_decoder: MessageDecoder,
+    sample_rate: u32,
     sample_count: u64,
-    active_message: Vec<bool>,
-    last_packet_received_at_sample: u64,
-    max_samples_until_next_packet: u64,
 }
 
 #[wasm_bindgen]
@@ -97,58 +98,64 @@ impl MessageTransceiver {
     }
 
     pub fn send_message(&mut self, message: &[u8]) {
-        if message.len() > MAX_MESSAGE_LEN {
-            panic!("message too long");
-        }
-        // first bit is used to mark new message or continuation
-        let useable_payload_bits = PAYLOAD_LEN * SYMBOL_BITS - 1;
-        // prefix message with length
-        let message_prefix = [message.len() as u8];
-        let mut bits = Vec::<bool>::with_capacity(
-            (message_prefix.len() + message.len()) * (8 + (8 / useable_payload_bits + 1)),
-        );
-        for (i, &byte) in message_prefix.iter().chain(message.iter()).enumerate() {
-            for j in 0..8 {
-                // insert a bit at beginning of each packet's payload
-                if (i * 8 + j) % useable_payload_bits == 0 {
-                    // set to 1 for start of message and 0 for following packets
-                    bits.push(i == 0);
-                }
-                bits.push((byte >> (7 - j)) & 1 == 1);
-            }
-        }
-        let mut symbols = Vec::<u8>::with_capacity(bits.len() / SYMBOL_BITS + 1);
-        for i in (0..bits.len()).step_by(SYMBOL_BITS) {
-            let mut symbol = 0u8;
-            for j in 0..SYMBOL_BITS {
-                if let Some(true) = bits.get(i + j) {
-                    symbol += 1 << (SYMBOL_BITS - j - 1);
-                }
-            }
-            debug_assert!((symbol as usize) < SYMBOL_COUNT);
-            symbols.push(symbol);
-        }
-        for i in (0..symbols.len()).step_by(PAYLOAD_LEN) {
-            let mut payload = [0u8; PAYLOAD_LEN];
-            for j in 0..PAYLOAD_LEN {
-                if let Some(&symbol) = symbols.get(i + j) {
-                    payload[j] = symbol;
-                }
-            }
+        for payload in self.message_encoder.encode(message) {
             (self.on_transmit)(payload, self.transceiver.send(&payload));
         }
     }
 
+    // Report the status of incoming messages to `on_message_status(status, received, total)`,
+    // the status is "started", "progress", "timeout" or "padding"
+    pub fn set_on_message_status(&mut self, on_message_status: js_sys::Function) {
+        self.on_message_status = Some(Box::new(move |status, received, total| {
+            on_message_status
+                .call3(
+                    &JsValue::NULL,
+                    &status.into(),
+                    &(received as u32).into(),
+                    &(total as u32).into(),
+                )
+                .unwrap();
+        }));
+        self.message_decoder.set_progress_events(true);
+    }
+
+    // Drop incomplete messages, when no packet was received for `timeout` seconds
+    pub fn set_message_timeout(&mut self, timeout: f64) {
+        self.message_decoder.set_timeout(timeout);
+    }
+
     pub fn push_sample(&mut self, sample: f32) {
         self.sample_count += 1;
+        let timestamp = (self.sample_count as f64) / (self.sample_rate as f64);
         if let Some(payload) = self.transceiver.push_sample(sample) {
             (self.on_received)(payload);
-            self.receive_message(payload);
+            self.message_decoder.push_payload(&payload, timestamp);
+        } else {
+            self.message_decoder.check_timeout(timestamp);
+        }
+        while let Some(event) = self.message_decoder.poll_event() {
+            let (status, received, total) = match event {
+                MessageEvent::Received(message) | MessageEvent::ReceivedAddressed(_, message) => {
+                    (self.on_received_message)(message.into_boxed_slice());
+                    continue;
+                }
+                MessageEvent::ChecksumMismatch => {
+                    log!("Dropped message with wrong checksum");
+                    continue;
+                }
+                MessageEvent::Rejected(err) => {
+                    log!("Dropped message: {}", err);
+                    continue;
+                }
+                MessageEvent::Started => ("started", 0, 0),
+                MessageEvent::Progress(received, total) => ("progress", received, total),
+                MessageEvent::TimedOut => ("timeout", 0, 0),
+                MessageEvent::InvalidPadding => ("padding", 0, 0),
+            };
+            if let Some(on_message_status) = self.on_message_status.as_mut() {
+                on_message_status(status, received, total);
+            }
         }
-    }
-
-    pub fn get_payload_len() -> usize {
-        return PAYLOAD_LEN;
     }
 
     pub fn get_beep_time() -> f32 {
@@ -163,12 +170,21 @@ impl MessageTransceiver {
         RELEASE_TIME
     }
 
-    pub fn get_symbol_mnemonics() -> String {
-        SYMBOL_MNEMONICS.to_owned()
+    pub fn format_code(payload: &[u8]) -> String {
+        let mut payload_clone: [u8; PAYLOAD_LEN] = Default::default();
+        payload_clone.clone_from_slice(payload);
+        format_code(&payload_clone)
+    }
+
+    // Throws the error message, if the code is invalid
+    pub fn parse_code(code: &str) -> Result<Box<[u8]>, JsValue> {
+        parse_code(code)
+            .map(|payload| payload.to_vec().into_boxed_slice())
+            .map_err(|err| err.to_string().into())
     }
 
     pub fn get_max_message_len() -> usize {
-        MAX_MESSAGE_LEN
+        DEFAULT_MAX_MESSAGE_LEN
     }
 }
 
@@ -184,63 +200,11 @@ impl MessageTransceiver {
             on_transmit: on_transmit,
             on_received: on_received,
             on_received_message: on_received_message,
+            on_message_status: None,
+            message_encoder: MessageEncoder::new(),
+            message_decoder: MessageDecoder::new(),
+            sample_rate,
             sample_count: 0,
-            active_message: Vec::new(),
-            last_packet_received_at_sample: 0,
-            max_samples_until_next_packet: ((BEEP_TIME * (PACKET_LEN as f32)
-                + MAX_TIME_BETWEEN_PACKETS)
-                * (sample_rate as f32))
-                .round() as u64,
-        }
-    }
-
-    fn receive_message(&mut self, payload: [u8; PAYLOAD_LEN]) {
-        let mut bits = Vec::<bool>::with_capacity(PAYLOAD_LEN * SYMBOL_BITS);
-        for &symbol in payload.iter() {
-            for i in (0..SYMBOL_BITS).rev() {
-                bits.push((symbol >> i) & 1 == 1);
-            }
-        }
-        // check for marker of new message
-        if bits[0] == true {
-            self.active_message.clear();
-        } else if self.active_message.len() == 0 {
-            return;
-        } else {
-            let age = self.sample_count - self.last_packet_received_at_sample;
-            if age > self.max_samples_until_next_packet {
-                self.active_message.clear();
-                return;
-            }
-        }
-        self.last_packet_received_at_sample = self.sample_count;
-        self.active_message.reserve(bits.len() - 1);
-        self.active_message.extend(bits.iter().skip(1));
-        let mut message = Vec::<u8>::with_capacity(self.active_message.len() / 8);
-        let mut message_len = -1;
-        for i in (0..self.active_message.len() / 8 * 8).step_by(8) {
-            let mut byte = 0u8;
-            for j in 0..8 {
-                if self.active_message[i + j] {
-                    byte += 1 << (8 - j - 1);
-                }
-            }
-            // first byte is message length
-            if i == 0 {
-                message_len = byte as isize;
-            } else if message.len() as isize >= message_len {
-                // check zero padding
-                if byte != 0 {
-                    self.active_message.clear();
-                    return;
-                }
-            } else {
-                message.push(byte)
-            }
-        }
-        if message_len != -1 && message.len() as isize >= message_len {
-            self.active_message.clear();
-            (self.on_received_message)(message.into_boxed_slice());
         }
     }
 }
@@ -0,0 +1,484 @@
+use std::collections::VecDeque;
+
+use super::message::{
+    bits_to_payload, fragment_header, payload_to_bits, read_bits, write_bits, MessageDecoder,
+    MessageEncoder, MessageEvent, FRAGMENT_INDEX_BITS, MESSAGE_ID_BITS, PAYLOAD_BITS,
+};
+use super::{DuplexMode, Transceiver, PACKET_LEN, PAYLOAD_LEN};
+
+// Maximum number of fragments that are sent before waiting for an acknowledgement
+pub const ARQ_WINDOW_LEN: usize = 8;
+// Number of consecutive rounds without progress before a transfer fails
+pub const ARQ_MAX_RETRIES: usize = 3;
+// The receiver acknowledges after no fragment was received for this number of packet durations.
+// A single lost fragment doesn't trigger an acknowledgement in the middle of a window.
+const ACK_DELAY_PACKETS: f64 = 2.5;
+// The sender retransmits, when no acknowledgement was received for this number of packet
+// durations after the end of its transmission
+const ACK_TIMEOUT_PACKETS: f64 = ACK_DELAY_PACKETS + 2.;
+// A received message is remembered for the time, in which the sender may repeat its fragments
+// because the final acknowledgement was lost, in number of packet durations
+const COMPLETED_TIMEOUT_PACKETS: f64 =
+    ((ARQ_MAX_RETRIES + 1) as f64) * ((ARQ_WINDOW_LEN as f64) + ACK_TIMEOUT_PACKETS);
+// Acknowledgements are invalid fragments with the fragment index 255 and the fragment count 1.
+// They contain the message ID, the number of consecutive received fragments and a bitmap of
+// the following fragments.
+const ACK_INDEX: usize = (1 << FRAGMENT_INDEX_BITS) - 1;
+const ACK_BASE_BITS: usize = 9;
+const ACK_BITMAP_BITS: usize =
+    PAYLOAD_BITS - MESSAGE_ID_BITS - 2 * FRAGMENT_INDEX_BITS - ACK_BASE_BITS;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ArqEvent {
+    Received(Vec<u8>),
+    // All fragments of the sent message were acknowledged
+    Delivered,
+    // The sent message was not acknowledged after `ARQ_MAX_RETRIES` retransmissions or the
+    // receiver couldn't decode it after `ARQ_MAX_RETRIES` repeated transfers
+    Failed,
+}
+
+struct Transfer {
+    id: usize,
+    fragments: Vec<[u8; PAYLOAD_LEN]>,
+    acknowledged: Vec<bool>,
+    retries: usize,
+    // Number of times the receiver dropped the fragments, because the message couldn't be decoded
+    restarts: usize,
+    // Time when the acknowledgement of the current window is overdue
+    ack_deadline: Option<f64>,
+}
+
+struct Reception {
+    id: usize,
+    fragments: Vec<Option<[u8; PAYLOAD_LEN]>>,
+    last_fragment_at: f64,
+    ack_pending: bool,
+}
+
+// A received message, whose fragments may be repeated, if the final acknowledgement was lost
+struct Completed {
+    id: usize,
+    fragments: Vec<[u8; PAYLOAD_LEN]>,
+    completed_at: f64,
+    last_fragment_at: f64,
+    ack_pending: bool,
+}
+
+// Reliable transfer of messages between two devices with selective repeat of lost fragments.
+// Messages are sent as fragments (see `MessageEncoder::set_fragmented`) in windows of up to
+// `ARQ_WINDOW_LEN` fragments. The receiver acknowledges the received fragments after each
+// window and the sender repeats the missing ones. The final acknowledgement is only sent, when
+// the message was decoded. Otherwise, all fragments are requested again. The audio for the speaker is generated by
+// the transmitter of the transceiver (see method `transceiver`).
+pub struct ArqSession {
+    transceiver: Transceiver,
+    encoder: MessageEncoder,
+    sample_rate: u32,
+    packet_time: f64,
+    sample_count: u64,
+    transfer: Option<Transfer>,
+    reception: Option<Reception>,
+    completed: Option<Completed>,
+    events: VecDeque<ArqEvent>,
+}
+
+impl ArqSession {
+    pub fn new(sample_rate: u32) -> Self {
+        Self::with_transceiver(Transceiver::new(sample_rate))
+    }
+
+    // Our own transmissions are dropped by the receiver (see `DuplexMode::DropOwn`)
+    pub fn with_transceiver(mut transceiver: Transceiver) -> Self {
+        transceiver.set_duplex_mode(DuplexMode::DropOwn);
+        let mut encoder = MessageEncoder::new();
+        encoder.set_fragmented(true);
+        Self {
+            sample_rate: transceiver.transmitter().sample_rate(),
+            packet_time: (transceiver.profile().beep_time() as f64) * (PACKET_LEN as f64),
+            transceiver,
+            encoder,
+            sample_count: 0,
+            transfer: None,
+            reception: None,
+            completed: None,
+            events: VecDeque::new(),
+        }
+    }
+
+    pub fn transceiver(&mut self) -> &mut Transceiver {
+        &mut self.transceiver
+    }
+
+    // Returns `true` while a sent message is not delivered or failed
+    pub fn is_sending(&self) -> bool {
+        self.transfer.is_some()
+    }
+
+    // Start the transfer of `message`. Only one message can be sent at a time.
+    pub fn send(&mut self, message: &[u8]) {
+        assert!(!self.is_sending(), "a message is already being sent");
+        let fragments = self.encoder.encode(message);
+        let (id, _, _) = fragment_header(&payload_to_bits(&fragments[0]));
+        self.transfer = Some(Transfer {
+            id,
+            acknowledged: vec![false; fragments.len()],
+            fragments,
+            retries: 0,
+            restarts: 0,
+            ack_deadline: None,
+        });
+        self.send_window();
+    }
+
+    // Get the next received message or the result of a transfer
+    pub fn poll_event(&mut self) -> Option<ArqEvent> {
+        self.events.pop_front()
+    }
+
+    // Commit an audio sample from the microphone
+    pub fn push_sample(&mut self, sample: f32) {
+        self.sample_count += 1;
+        let now = self.now();
+        if let Some(payload) = self.transceiver.push_sample(sample) {
+            let bits = payload_to_bits(&payload);
+            match fragment_header(&bits) {
+                (id, ACK_INDEX, 1) => self.receive_ack(id, &bits, now),
+                (id, index, count) if index < count => {
+                    self.receive_fragment(id, index, count, payload, now)
+                }
+                _ => (),
+            }
+        }
+        let idle = self.transceiver.transmitter().is_idle();
+        if let Some(transfer) = self.transfer.as_mut() {
+            match transfer.ack_deadline {
+                None if idle => {
+                    transfer.ack_deadline = Some(now + ACK_TIMEOUT_PACKETS * self.packet_time)
+                }
+                Some(deadline) if now >= deadline => {
+                    transfer.retries += 1;
+                    if transfer.retries > ARQ_MAX_RETRIES {
+                        self.transfer = None;
+                        self.events.push_back(ArqEvent::Failed);
+                    } else {
+                        self.send_window();
+                    }
+                }
+                _ => (),
+            }
+        }
+        let ack_delay = ACK_DELAY_PACKETS * self.packet_time;
+        if let Some(completed) = self.completed.as_mut() {
+            if now - completed.completed_at >= COMPLETED_TIMEOUT_PACKETS * self.packet_time {
+                self.completed = None;
+            } else if idle && completed.ack_pending && now - completed.last_fragment_at >= ack_delay
+            {
+                completed.ack_pending = false;
+                let ack = ack_payload(completed.id, &vec![true; completed.fragments.len()]);
+                self.transceiver.transmitter().enqueue(&ack);
+            }
+        }
+        if let Some(reception) = self.reception.as_mut() {
+            if idle && reception.ack_pending && now - reception.last_fragment_at >= ack_delay {
+                reception.ack_pending = false;
+                let received: Vec<bool> = reception.fragments.iter().map(Option::is_some).collect();
+                let ack = ack_payload(reception.id, &received);
+                self.transceiver.transmitter().enqueue(&ack);
+            }
+        }
+    }
+
+    fn now(&self) -> f64 {
+        (self.sample_count as f64) / (self.sample_rate as f64)
+    }
+
+    // Queue the first missing fragments, that can be acknowledged together
+    fn send_window(&mut self) {
+        let transfer = match self.transfer.as_mut() {
+            Some(transfer) => transfer,
+            None => return,
+        };
+        let base = transfer
+            .acknowledged
+            .iter()
+            .take_while(|&&acked| acked)
+            .count();
+        let window: Vec<usize> = (base..transfer.fragments.len())
+            .filter(|&index| index <= base + ACK_BITMAP_BITS && !transfer.acknowledged[index])
+            .take(ARQ_WINDOW_LEN)
+            .collect();
+        transfer.ack_deadline = None;
+        for &index in window.iter() {
+            self.transceiver
+                .transmitter()
+                .enqueue(&transfer.fragments[index]);
+        }
+    }
+
+    fn receive_ack(&mut self, id: usize, bits: &[bool], now: f64) {
+        let transfer = match self.transfer.as_mut() {
+            Some(transfer) if transfer.id == id => transfer,
+            _ => return,
+        };
+        let start = MESSAGE_ID_BITS + 2 * FRAGMENT_INDEX_BITS;
+        let base = read_bits(bits, start, ACK_BASE_BITS);
+        let bitmap = read_bits(bits, start + ACK_BASE_BITS, ACK_BITMAP_BITS);
+        // The acknowledgement contains the state of the receiver, that drops all fragments if
+        // the message couldn't be decoded
+        let mut progress = false;
+        let mut dropped = false;
+        for (index, acked) in transfer.acknowledged.iter_mut().enumerate() {
+            if index > base + ACK_BITMAP_BITS {
+                break;
+            }
+            let received =
+                index < base || (index > base && bitmap & (1 << (index - base - 1)) != 0);
+            progress |= received && !*acked;
+            dropped |= !received && *acked;
+            *acked = received;
+        }
+        if dropped {
+            transfer.restarts += 1;
+            if transfer.restarts > ARQ_MAX_RETRIES {
+                self.transfer = None;
+                self.events.push_back(ArqEvent::Failed);
+                return;
+            }
+        } else if progress {
+            transfer.retries = 0;
+        }
+        if transfer.acknowledged.iter().all(|&acked| acked) {
+            self.transfer = None;
+            self.events.push_back(ArqEvent::Delivered);
+        } else if transfer
+            .ack_deadline
+            .map_or(false, |deadline| now < deadline)
+        {
+            // The acknowledgement of the current window arrived
+            self.send_window();
+        }
+    }
+
+    fn receive_fragment(
+        &mut self,
+        id: usize,
+        index: usize,
+        count: usize,
+        payload: [u8; PAYLOAD_LEN],
+        now: f64,
+    ) {
+        // The sender repeats fragments of the last message, if the final acknowledgement was
+        // lost. A new message with the same ID and count has different fragments.
+        if let Some(completed) = self.completed.as_mut() {
+            if completed.id == id
+                && completed.fragments.len() == count
+                && completed.fragments[index] == payload
+            {
+                completed.last_fragment_at = now;
+                completed.ack_pending = true;
+                return;
+            }
+        }
+        // Another message replaces the current one
+        let reception = match self.reception.as_mut() {
+            Some(reception) if reception.id == id && reception.fragments.len() == count => {
+                reception
+            }
+            _ => self.reception.insert(Reception {
+                id,
+                fragments: vec![None; count],
+                last_fragment_at: now,
+                ack_pending: false,
+            }),
+        };
+        reception.last_fragment_at = now;
+        reception.ack_pending = true;
+        if reception.fragments[index].is_some() {
+            return;
+        }
+        reception.fragments[index] = Some(payload);
+        if reception.fragments.iter().any(Option::is_none) {
+            return;
+        }
+        let mut decoder = MessageDecoder::new();
+        decoder.set_fragmented(true);
+        for fragment in reception.fragments.iter().flatten() {
+            decoder.push_payload(fragment, now);
+        }
+        let message = std::iter::from_fn(|| decoder.poll_event()).find_map(|event| match event {
+            MessageEvent::Received(message) | MessageEvent::ReceivedAddressed(_, message) => {
+                Some(message)
+            }
+            _ => None,
+        });
+        match message {
+            Some(message) => {
+                self.events.push_back(ArqEvent::Received(message));
+                self.completed = Some(Completed {
+                    id,
+                    fragments: reception.fragments.iter().flatten().copied().collect(),
+                    completed_at: now,
+                    last_fragment_at: now,
+                    ack_pending: true,
+                });
+                self.reception = None;
+            }
+            // Request all fragments again
+            None => reception
+                .fragments
+                .iter_mut()
+                .for_each(|fragment| *fragment = None),
+        }
+    }
+}
+
+fn ack_payload(id: usize, received: &[bool]) -> [u8; PAYLOAD_LEN] {
+    let base = received.iter().take_while(|&&received| received).count();
+    let bitmap = (0..ACK_BITMAP_BITS)
+        .filter(|&i| received.get(base + 1 + i) == Some(&true))
+        .fold(0, |bitmap, i| bitmap | (1 << i));
+    let mut bits = Vec::with_capacity(PAYLOAD_BITS);
+    write_bits(&mut bits, id, MESSAGE_ID_BITS);
+    write_bits(&mut bits, ACK_INDEX, FRAGMENT_INDEX_BITS);
+    write_bits(&mut bits, 0, FRAGMENT_INDEX_BITS);
+    write_bits(&mut bits, base, ACK_BASE_BITS);
+    write_bits(&mut bits, bitmap, ACK_BITMAP_BITS);
+    bits_to_payload(&bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::channel::Channel;
+    use crate::TransmitEvent;
+    use std::ops::Range;
+
+    const SAMPLE_RATE: u32 = 44100;
+
+    // Exchange audio between the sessions through the channels until both are idle.
+    // The audio from each session is muted during the time range in `muted`.
+    // Returns the events and the number of transmitted packets of each session.
+    fn exchange(
+        sessions: &mut [ArqSession; 2],
+        channels: &mut [Channel; 2],
+        muted: [Range<f32>; 2],
+        max_time: f32,
+    ) -> [(Vec<ArqEvent>, usize); 2] {
+        let mut results: [(Vec<ArqEvent>, usize); 2] = Default::default();
+        let chunk_time = 0.5;
+        let chunk_len = ((SAMPLE_RATE as f32) * chunk_time) as usize;
+        let mut buffers = [vec![0.; chunk_len], vec![0.; chunk_len]];
+        for chunk in 0..((max_time / chunk_time) as usize) {
+            for (session, buffer) in sessions.iter_mut().zip(buffers.iter_mut()) {
+                session.transceiver().transmitter().fill(buffer);
+            }
+            for i in 0..2 {
+                if muted[i].contains(&((chunk as f32) * chunk_time)) {
+                    buffers[i].iter_mut().for_each(|sample| *sample = 0.);
+                }
+                let samples = channels[i].process(&buffers[i]);
+                let receiver = &mut sessions[1 - i];
+                for &sample in samples.iter() {
+                    receiver.push_sample(sample);
+                }
+            }
+            for (session, results) in sessions.iter_mut().zip(results.iter_mut()) {
+                results
+                    .0
+                    .extend(std::iter::from_fn(|| session.poll_event()));
+                while let Some(event) = session.transceiver().transmitter().poll_event() {
+                    if let TransmitEvent::Started { .. } = event {
+                        results.1 += 1;
+                    }
+                }
+            }
+            if sessions.iter().all(|session| !session.is_sending()) {
+                break;
+            }
+        }
+        results
+    }
+
+    // Test that lost fragments and acknowledgements are repeated
+    #[test]
+    fn test_transfer() {
+        let message: Vec<u8> = (0..30).collect();
+        let mut sessions = [ArqSession::new(SAMPLE_RATE), ArqSession::new(SAMPLE_RATE)];
+        let mut channels = [
+            Channel::new(SAMPLE_RATE, 1).white_noise(10.).tilt(-3.),
+            Channel::new(SAMPLE_RATE, 2).white_noise(10.).tilt(-3.),
+        ];
+        sessions[0].send(&message);
+        let fragment_count = sessions[0].transfer.as_ref().unwrap().fragments.len();
+        // The second fragment and the second acknowledgement are lost
+        let [(sender_events, sent), (receiver_events, acks)] =
+            exchange(&mut sessions, &mut channels, [2. ..3., 28. ..34.], 120.);
+        assert_eq!(sender_events, vec![ArqEvent::Delivered]);
+        assert_eq!(receiver_events, vec![ArqEvent::Received(message)]);
+        assert!(sent > fragment_count, "no fragment was repeated");
+        assert!(acks > 2, "no acknowledgement was repeated");
+    }
+
+    // Test that a message with the ID of the previous message isn't taken for a repetition
+    #[test]
+    fn test_same_message_id() {
+        let messages: [Vec<u8>; 2] = [(0..30).collect(), (30..60).collect()];
+        let mut sessions = [ArqSession::new(SAMPLE_RATE), ArqSession::new(SAMPLE_RATE)];
+        let mut channels = [
+            Channel::new(SAMPLE_RATE, 1).white_noise(10.),
+            Channel::new(SAMPLE_RATE, 2).white_noise(10.),
+        ];
+        for message in messages.iter() {
+            // A new session starts with the same message ID
+            sessions[0] = ArqSession::new(SAMPLE_RATE);
+            sessions[0].send(message);
+            let [(sender_events, _), (receiver_events, _)] =
+                exchange(&mut sessions, &mut channels, [0. ..0., 0. ..0.], 120.);
+            assert_eq!(sender_events, vec![ArqEvent::Delivered]);
+            assert_eq!(receiver_events, vec![ArqEvent::Received(message.clone())]);
+        }
+    }
+
+    // Test that the fragments of a message, that can't be decoded, are requested again
+    #[test]
+    fn test_corrupted_message() {
+        let message: Vec<u8> = (0..30).collect();
+        let mut encoder = MessageEncoder::new();
+        encoder.set_fragmented(true);
+        let fragments = encoder.encode(&message);
+        let mut corrupted = fragments.clone();
+        corrupted[2][PAYLOAD_LEN - 1] ^= 1;
+        let mut session = ArqSession::new(SAMPLE_RATE);
+        for fragments in [corrupted, fragments].iter() {
+            for &fragment in fragments.iter() {
+                let (id, index, count) = fragment_header(&payload_to_bits(&fragment));
+                session.receive_fragment(id, index, count, fragment, 0.);
+            }
+        }
+        assert_eq!(session.poll_event(), Some(ArqEvent::Received(message)));
+        assert_eq!(session.poll_event(), None);
+    }
+
+    // Test that a transfer fails without acknowledgements
+    #[test]
+    fn test_transfer_failure() {
+        let mut session = ArqSession::new(SAMPLE_RATE);
+        session.send(b"Hi");
+        let mut buffer = vec![0.; 1000];
+        let mut sent = 0;
+        while session.is_sending() {
+            session.transceiver().transmitter().fill(&mut buffer);
+            for _ in 0..buffer.len() {
+                session.push_sample(0.);
+            }
+            while let Some(event) = session.transceiver().transmitter().poll_event() {
+                if let TransmitEvent::Started { .. } = event {
+                    sent += 1;
+                }
+            }
+        }
+        assert_eq!(session.poll_event(), Some(ArqEvent::Failed));
+        assert_eq!(sent, 3 * (ARQ_MAX_RETRIES + 1));
+    }
+}
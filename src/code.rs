@@ -0,0 +1,152 @@
+use std::error;
+use std::fmt;
+
+use super::{PAYLOAD_LEN, SYMBOL_BITS};
+
+// Crockford's Base32, the index is the value of a symbol.
+// The check character is the payload modulo 37 and can use 5 additional characters.
+const CODE_ALPHABET: &str = "0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+const CHECK_ALPHABET: &str = "0123456789ABCDEFGHJKMNPQRSTVWXYZ*~$=U";
+const GROUP_LEN: usize = 4;
+const GROUP_SEPARATOR: char = '-';
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CodeError {
+    InvalidCharacter(char),
+    // Number of characters without separators
+    InvalidLength(usize),
+    ChecksumMismatch,
+}
+
+impl fmt::Display for CodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CodeError::InvalidCharacter(c) => write!(f, "invalid character {:?}", c),
+            CodeError::InvalidLength(len) => write!(
+                f,
+                "code must have {} characters but has {}",
+                PAYLOAD_LEN + 1,
+                len
+            ),
+            CodeError::ChecksumMismatch => write!(f, "check character doesn't match"),
+        }
+    }
+}
+
+impl error::Error for CodeError {}
+
+// Format a payload as a human-readable code with one character per symbol and a check
+// character, in groups of 4 characters (e.g. `0123-4567-89P`)
+pub fn format_code(payload: &[u8; PAYLOAD_LEN]) -> String {
+    let alphabet: Vec<char> = CODE_ALPHABET.chars().collect();
+    let check_alphabet: Vec<char> = CHECK_ALPHABET.chars().collect();
+    let mut chars: Vec<char> = payload
+        .iter()
+        .map(|&symbol| alphabet[symbol as usize])
+        .collect();
+    chars.push(check_alphabet[check_value(payload)]);
+    let groups: Vec<String> = chars
+        .chunks(GROUP_LEN)
+        .map(|group| group.iter().collect())
+        .collect();
+    groups.join(&GROUP_SEPARATOR.to_string())
+}
+
+// Parse a code from function `format_code`. Case, separators and whitespace are ignored and
+// the confusable characters `O`, `I` and `L` are read as `0` and `1`.
+pub fn parse_code(code: &str) -> Result<[u8; PAYLOAD_LEN], CodeError> {
+    let chars: Vec<char> = code
+        .chars()
+        .filter(|&c| c != GROUP_SEPARATOR && !c.is_whitespace())
+        .map(|c| match c.to_ascii_uppercase() {
+            'O' => '0',
+            'I' | 'L' => '1',
+            c => c,
+        })
+        .collect();
+    if chars.len() != PAYLOAD_LEN + 1 {
+        return Err(CodeError::InvalidLength(chars.len()));
+    }
+    let mut payload = [0; PAYLOAD_LEN];
+    for (symbol, &c) in payload.iter_mut().zip(chars.iter()) {
+        *symbol = CODE_ALPHABET
+            .chars()
+            .position(|alphabet_c| alphabet_c == c)
+            .ok_or(CodeError::InvalidCharacter(c))? as u8;
+    }
+    let check_c = chars[PAYLOAD_LEN];
+    let check = CHECK_ALPHABET
+        .chars()
+        .position(|alphabet_c| alphabet_c == check_c)
+        .ok_or(CodeError::InvalidCharacter(check_c))?;
+    if check != check_value(&payload) {
+        return Err(CodeError::ChecksumMismatch);
+    }
+    Ok(payload)
+}
+
+// The payload as number modulo 37
+fn check_value(payload: &[u8; PAYLOAD_LEN]) -> usize {
+    let value = (payload.iter()).fold(0u64, |value, &symbol| {
+        (value << SYMBOL_BITS) | symbol as u64
+    });
+    (value % CHECK_ALPHABET.len() as u64) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SYMBOL_COUNT;
+
+    // Test formatting and parsing of codes
+    #[test]
+    fn test_codes() {
+        assert_eq!(CODE_ALPHABET.len(), SYMBOL_COUNT);
+        assert_eq!(CHECK_ALPHABET.len(), 37);
+        let payload = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
+        // 0x110_c853_1d09 % 37 == 22
+        assert_eq!(format_code(&payload), "0123-4567-89P");
+        assert_eq!(format_code(&[31; PAYLOAD_LEN]), "ZZZZ-ZZZZ-ZZX");
+        for code in [
+            "0123-4567-89P",
+            "o123 4567 89p",
+            "OI23456789-P",
+            "0l2345678 9P",
+        ]
+        .iter()
+        {
+            assert_eq!(parse_code(code), Ok(payload), "{:?}", code);
+        }
+        for i in 0..100 {
+            let payload = crate::test_utils::rand_payload(i);
+            assert_eq!(parse_code(&format_code(&payload)), Ok(payload));
+        }
+    }
+
+    // Test that invalid codes are rejected
+    #[test]
+    fn test_invalid_codes() {
+        assert_eq!(parse_code(""), Err(CodeError::InvalidLength(0)));
+        assert_eq!(
+            parse_code("0123-4567-89"),
+            Err(CodeError::InvalidLength(10))
+        );
+        assert_eq!(
+            parse_code("0123-4567-8UP"),
+            Err(CodeError::InvalidCharacter('U'))
+        );
+        assert_eq!(
+            parse_code("0123-4567-89!"),
+            Err(CodeError::InvalidCharacter('!'))
+        );
+        // Typo and swapped characters
+        assert_eq!(
+            parse_code("0123-4568-89P"),
+            Err(CodeError::ChecksumMismatch)
+        );
+        assert_eq!(
+            parse_code("0123-4576-89P"),
+            Err(CodeError::ChecksumMismatch)
+        );
+    }
+}
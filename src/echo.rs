@@ -0,0 +1,82 @@
+use std::collections::VecDeque;
+
+// Step size of the NLMS algorithm
+const STEP_SIZE: f32 = 0.2;
+// Regularization of the normalization, avoids large steps with a quiet reference signal
+const REGULARIZATION: f32 = 1e-6;
+
+// Adaptive NLMS filter, that estimates the echo of the reference signal in the input and
+// subtracts it
+pub(crate) struct EchoCanceller {
+    weights: Vec<f32>,
+    // Most recent reference samples, the newest first
+    reference: VecDeque<f32>,
+    reference_energy: f64,
+}
+
+impl EchoCanceller {
+    pub fn new(len: usize) -> Self {
+        assert!(len > 0);
+        Self {
+            weights: vec![0.; len],
+            reference: vec![0.; len].into(),
+            reference_energy: 0.,
+        }
+    }
+
+    // Commit the next sample of the reference signal and the input, returns the input
+    // without the estimated echo
+    pub fn process(&mut self, reference: f32, input: f32) -> f32 {
+        let oldest = self.reference.pop_back().unwrap();
+        self.reference.push_front(reference);
+        self.reference_energy += (reference as f64).powi(2) - (oldest as f64).powi(2);
+        // Avoid drift of the running sum
+        self.reference_energy = self.reference_energy.max(0.);
+        let echo: f32 = self
+            .weights
+            .iter()
+            .zip(self.reference.iter())
+            .map(|(w, x)| w * x)
+            .sum();
+        let error = input - echo;
+        if self.reference_energy > 0. {
+            let step = STEP_SIZE * error / (self.reference_energy as f32 + REGULARIZATION);
+            for (w, x) in self.weights.iter_mut().zip(self.reference.iter()) {
+                *w += step * x;
+            }
+        }
+        error
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::prelude::*;
+
+    fn energy(samples: &[f32]) -> f32 {
+        samples.iter().map(|x| x * x).sum()
+    }
+
+    // Test that the echo of white noise is removed, while another signal passes
+    #[test]
+    fn test_echo_cancellation() {
+        const LEN: usize = 16000;
+        const DELAY: usize = 40;
+        let mut rng = SmallRng::seed_from_u64(0);
+        let reference: Vec<f32> = (0..LEN).map(|_| rng.gen_range(-0.5, 0.5)).collect();
+        let echo = |i: usize| {
+            let at = |delay: usize| i.checked_sub(delay).map_or(0., |i| reference[i]);
+            0.8 * at(DELAY) - 0.3 * at(DELAY + 7) + 0.1 * at(DELAY + 20)
+        };
+        let other = |i: usize| 0.01 * (i as f32 * 0.3).sin();
+        let mut echo_canceller = EchoCanceller::new(2 * DELAY);
+        let output: Vec<f32> = (0..LEN)
+            .map(|i| echo_canceller.process(reference[i], echo(i) + other(i)))
+            .collect();
+        // Compare the residual echo after convergence
+        let residual: Vec<f32> = (LEN / 2..LEN).map(|i| output[i] - other(i)).collect();
+        let echo: Vec<f32> = (LEN / 2..LEN).map(echo).collect();
+        assert!(energy(&residual) < energy(&echo) * 1e-3);
+    }
+}
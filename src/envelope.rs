@@ -0,0 +1,70 @@
+use std::f32::consts::PI;
+
+use super::Profile;
+
+// Amplitude envelope of the beeps
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Envelope {
+    // Linear attack and release with `attack_time` and `release_time` of the profile
+    Linear,
+    // Raised cosine attack and release with `attack_time` and `release_time` of the profile.
+    // The smooth ramps avoid clicks on some speakers and reduce the leakage into distant
+    // symbols, but leak more into adjacent symbols.
+    RaisedCosine,
+    // Gaussian bell with the standard deviation `sigma` relative to `beep_time`, must be
+    // positive. With a small `sigma` (e.g. `0.15`) the leakage into adjacent symbols is reduced.
+    Gaussian { sigma: f32 },
+    // Tukey window, `taper` is the tapered fraction of `beep_time` in the range `0.` to `1.`
+    // (`0.` is rectangular and `1.` is a Hann window)
+    Tukey { taper: f32 },
+}
+
+impl Default for Envelope {
+    fn default() -> Self {
+        Envelope::Linear
+    }
+}
+
+impl Envelope {
+    // Panics if the parameters are out of range
+    pub(crate) fn validate(&self) {
+        match *self {
+            Envelope::Gaussian { sigma } => {
+                assert!(sigma > 0., "sigma of the envelope must be positive")
+            }
+            Envelope::Tukey { taper } => assert!(
+                (0. ..=1.).contains(&taper),
+                "taper of the envelope must be in the range 0 to 1"
+            ),
+            Envelope::Linear | Envelope::RaisedCosine => {}
+        }
+    }
+
+    // Get the amplitude at time `t` of a beep with the timings of `profile`
+    pub fn amplitude(&self, t: f32, profile: &Profile) -> f32 {
+        let beep_time = profile.beep_time();
+        match *self {
+            Envelope::Linear => {
+                (t / profile.attack_time()).min(1.)
+                    * ((beep_time - t) / profile.release_time()).min(1.)
+            }
+            Envelope::RaisedCosine => {
+                let ramp = |x: f32| 0.5 - 0.5 * (PI * x.min(1.)).cos();
+                ramp(t / profile.attack_time()) * ramp((beep_time - t) / profile.release_time())
+            }
+            Envelope::Gaussian { sigma } => {
+                let x = (t - beep_time / 2.) / (sigma * beep_time);
+                (-0.5 * x * x).exp()
+            }
+            Envelope::Tukey { taper } => {
+                let taper_time = taper * beep_time / 2.;
+                let ramp = |x: f32| 0.5 - 0.5 * (PI * x.min(1.)).cos();
+                if taper_time <= 0. {
+                    1.
+                } else {
+                    ramp(t / taper_time) * ramp((beep_time - t) / taper_time)
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use std::collections::VecDeque;
+
+// Duration of the sliding window for the level measurements
+pub const LEVEL_WINDOW_TIME: f32 = 0.5;
+// The sliding window advances in blocks of this duration
+const LEVEL_BLOCK_TIME: f32 = 0.05;
+// Samples with an absolute value of atleast `CLIPPING_LEVEL` are counted as clipped
+pub const CLIPPING_LEVEL: f32 = 0.99;
+// Warn about clipping, when the fraction of clipped samples is above this value
+const CLIPPING_WARNING_FRACTION: f32 = 0.001;
+// Warn about silence, when the RMS is below this value (-70 dBFS)
+const SILENCE_WARNING_RMS: f32 = 0.000_316;
+// Warn when the condition persists for this duration
+pub const LEVEL_WARNING_TIME: f32 = 2.0;
+
+// Level of the input audio in the sliding window of `LEVEL_WINDOW_TIME`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct InputLevel {
+    pub rms: f32,
+    pub peak: f32,
+    // Fraction of clipped samples
+    pub clipped: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LevelWarning {
+    // The input is clipping, the input gain should be reduced
+    Clipping,
+    // The input is silent, the microphone might be muted
+    Silence,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Block {
+    sum_squares: f32,
+    peak: f32,
+    clipped: usize,
+    len: usize,
+}
+
+pub(crate) struct LevelMeter {
+    block_len: usize,
+    blocks: VecDeque<Block>,
+    current_block: Block,
+    level: InputLevel,
+    warning_blocks: usize,
+    // Number of consecutive blocks with clipping or silence
+    clipping_blocks: usize,
+    silence_blocks: usize,
+    warnings: VecDeque<LevelWarning>,
+}
+
+impl LevelMeter {
+    pub fn new(sample_rate: u32) -> Self {
+        let block_len = (((sample_rate as f32) * LEVEL_BLOCK_TIME).round() as usize).max(1);
+        Self {
+            block_len,
+            blocks: VecDeque::new(),
+            current_block: Default::default(),
+            level: Default::default(),
+            warning_blocks: (LEVEL_WARNING_TIME / LEVEL_BLOCK_TIME).round() as usize,
+            clipping_blocks: 0,
+            silence_blocks: 0,
+            warnings: VecDeque::new(),
+        }
+    }
+
+    pub fn push_sample(&mut self, sample: f32) {
+        let block = &mut self.current_block;
+        block.sum_squares += sample * sample;
+        block.peak = block.peak.max(sample.abs());
+        if sample.abs() >= CLIPPING_LEVEL {
+            block.clipped += 1;
+        }
+        block.len += 1;
+        if block.len < self.block_len {
+            return;
+        }
+        let block = std::mem::take(&mut self.current_block);
+        self.blocks.push_back(block);
+        if self.blocks.len() > (LEVEL_WINDOW_TIME / LEVEL_BLOCK_TIME).round() as usize {
+            self.blocks.pop_front();
+        }
+        let (mut sum_squares, mut peak, mut clipped, mut len) = (0., 0f32, 0, 0);
+        for block in self.blocks.iter() {
+            sum_squares += block.sum_squares;
+            peak = peak.max(block.peak);
+            clipped += block.clipped;
+            len += block.len;
+        }
+        self.level = InputLevel {
+            rms: (sum_squares / (len as f32)).sqrt(),
+            peak,
+            clipped: (clipped as f32) / (len as f32),
+        };
+
+        // Check the last block for conditions that must persist to cause a warning
+        let block_clipped = (block.clipped as f32) / (block.len as f32);
+        let block_rms = (block.sum_squares / (block.len as f32)).sqrt();
+        Self::update_condition(
+            &mut self.clipping_blocks,
+            block_clipped > CLIPPING_WARNING_FRACTION,
+            self.warning_blocks,
+            LevelWarning::Clipping,
+            &mut self.warnings,
+        );
+        Self::update_condition(
+            &mut self.silence_blocks,
+            block_rms < SILENCE_WARNING_RMS,
+            self.warning_blocks,
+            LevelWarning::Silence,
+            &mut self.warnings,
+        );
+    }
+
+    fn update_condition(
+        blocks: &mut usize,
+        condition: bool,
+        warning_blocks: usize,
+        warning: LevelWarning,
+        warnings: &mut VecDeque<LevelWarning>,
+    ) {
+        if !condition {
+            *blocks = 0;
+            return;
+        }
+        *blocks += 1;
+        // Warn only once, until the condition ends. Repeated warnings that were not polled
+        // yet are merged, this keeps the queue bounded.
+        if *blocks == warning_blocks && !warnings.contains(&warning) {
+            warnings.push_back(warning);
+        }
+    }
+
+    pub fn level(&self) -> InputLevel {
+        self.level
+    }
+
+    pub fn poll_warning(&mut self) -> Option<LevelWarning> {
+        self.warnings.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 8000;
+
+    fn push_for(level_meter: &mut LevelMeter, time: f32, sample: impl Fn(usize) -> f32) {
+        for i in 0..((SAMPLE_RATE as f32) * time) as usize {
+            level_meter.push_sample(sample(i));
+        }
+    }
+
+    // Test level measurements and warnings for clipping and silence
+    #[test]
+    fn test_level_meter() {
+        let mut level_meter = LevelMeter::new(SAMPLE_RATE);
+        // Square wave
+        push_for(&mut level_meter, LEVEL_WINDOW_TIME, |i| {
+            if i % 20 < 10 {
+                0.5
+            } else {
+                -0.5
+            }
+        });
+        let level = level_meter.level();
+        assert!((level.rms - 0.5).abs() < 1e-3);
+        assert!((level.peak - 0.5).abs() < 1e-3);
+        assert_eq!(level.clipped, 0.);
+
+        // Clipping
+        push_for(&mut level_meter, LEVEL_WARNING_TIME * 0.9, |i| {
+            if i % 20 < 10 {
+                1.
+            } else {
+                -0.5
+            }
+        });
+        let level = level_meter.level();
+        assert!((level.peak - 1.).abs() < 1e-3);
+        assert!((level.clipped - 0.5).abs() < 1e-3);
+        assert_eq!(level_meter.poll_warning(), None);
+        push_for(&mut level_meter, LEVEL_WARNING_TIME, |_| 1.);
+        assert_eq!(level_meter.poll_warning(), Some(LevelWarning::Clipping));
+        assert_eq!(level_meter.poll_warning(), None);
+
+        // Silence
+        push_for(&mut level_meter, LEVEL_WARNING_TIME * 2., |_| 0.);
+        assert_eq!(level_meter.level(), Default::default());
+        assert_eq!(level_meter.poll_warning(), Some(LevelWarning::Silence));
+        assert_eq!(level_meter.poll_warning(), None);
+    }
+
+    // Test that repeated warnings are merged, while they are not polled
+    #[test]
+    fn test_merged_warnings() {
+        let mut level_meter = LevelMeter::new(SAMPLE_RATE);
+        for _ in 0..10 {
+            push_for(&mut level_meter, LEVEL_WARNING_TIME * 1.5, |_| 1.);
+            push_for(&mut level_meter, LEVEL_WARNING_TIME * 1.5, |_| 0.);
+        }
+        assert_eq!(level_meter.poll_warning(), Some(LevelWarning::Clipping));
+        assert_eq!(level_meter.poll_warning(), Some(LevelWarning::Silence));
+        assert_eq!(level_meter.poll_warning(), None);
+    }
+}
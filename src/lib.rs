@@ -1,16 +1,39 @@
 extern crate goertzel;
 extern crate reed_solomon;
 
+pub mod arq;
+mod code;
+mod echo;
+mod envelope;
+mod level;
+pub mod message;
+mod profile;
+mod resample;
+mod transmit;
+
+pub use code::{format_code, parse_code, CodeError};
+pub use envelope::Envelope;
+pub use level::{InputLevel, LevelWarning, CLIPPING_LEVEL, LEVEL_WARNING_TIME, LEVEL_WINDOW_TIME};
+pub use profile::{Profile, ProfileError};
+pub use resample::Resampler;
+pub use transmit::{
+    DuplexMode, PacketId, TransmitEvent, TransmitStream, MAX_TRANSMIT_EVENTS, TRANSMIT_TAIL_TIME,
+};
+
+use echo::EchoCanceller;
+use level::LevelMeter;
+
+use std::collections::VecDeque;
 use std::f32;
 use std::f32::consts::PI;
 
-const SEMITONE: f32 = 1.05946311;
-const BASE_FREQ: f32 = 1760.0;
+// Timings of the default profile
 pub const BEEP_TIME: f32 = 0.0872;
 pub const ATTACK_TIME: f32 = 0.012;
 pub const RELEASE_TIME: f32 = 0.012;
 pub const SYMBOL_BITS: usize = 5;
 pub const SYMBOL_COUNT: usize = 32; // 2usize.pow(SYMBOL_BITS as u32)
+#[deprecated(note = "use `format_code` and `parse_code` for human-readable payloads")]
 pub const SYMBOL_MNEMONICS: &str = "0123456789abcdefghijklmnopqrstuv"; // .len() == SYMBOL_COUNT
 type GF = reed_solomon::GF2_5; // SYMBOL_BITS
 const START_SYMBOLS_LEN: usize = 2;
@@ -31,46 +54,97 @@ macro_rules! mod_short {
     }};
 }
 
-#[derive(Clone, Copy, Default)]
+// A frame consists of `interleave_depth` interleaved packets
+#[derive(Clone)]
 struct Packet {
-    data: [u8; PACKET_LEN],
+    data: Vec<u8>,
     data_pos: usize,
     signal_quality: f32,
 }
 
+// Information about a received packet
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PacketMetadata {
+    // Number of start and payload symbols that were received without errors
+    pub correct_symbols: usize,
+    // Average SNR of the symbols
+    pub signal_quality: f32,
+    // Number of identical copies that were received (see `set_duplicate_window`)
+    pub repeat_count: usize,
+    // Number of samples pushed to the receiver when the packet was received
+    pub received_at: u64,
+    // Level of the input audio when the packet was received
+    pub input_level: InputLevel,
+}
+
+impl PacketMetadata {
+    #[inline]
+    fn quality(&self) -> (usize, f32) {
+        (self.correct_symbols, self.signal_quality)
+    }
+}
+
+struct PendingPacket {
+    payload: [u8; PAYLOAD_LEN],
+    metadata: PacketMetadata,
+    first_received_at: u64,
+}
+
 pub struct Transceiver {
     sample_rate: u32,
+    processing_rate: u32,
+    resampler: Option<Resampler>,
+    level_meter: LevelMeter,
+    profile: Profile,
+    transmitter: TransmitStream,
+    duplex_mode: DuplexMode,
+    echo_canceller: Option<EchoCanceller>,
+    sample_count: u64,
     measurement_count: u64,
     sample_buffer: Vec<f32>,
     sample_buffer_pos: usize,
     remaining_samples: f32,
     window_weights: Vec<f32>,
     rs_decoder: reed_solomon::Decoder<GF>,
-    rs_encoder: reed_solomon::Encoder<GF>,
     goertzel_filters: [goertzel::Parameters; SYMBOL_COUNT],
-    packets: [Packet; MEASUREMENTS_PER_SYMBOL * PACKET_LEN],
+    interleave_depth: usize,
+    packets: Vec<Packet>,
     packets_pos: usize,
     valid_packet: bool,
-    valid_packet_payload: [u8; PAYLOAD_LEN],
+    valid_packet_payloads: Vec<([u8; PAYLOAD_LEN], usize)>,
     valid_packet_quality: (usize, f32),
     first_valid_packet_age: usize,
+    duplicate_window: u64,
+    pending_packets: VecDeque<PendingPacket>,
 }
 
 impl Transceiver {
-    #[inline]
-    fn calc_freq(symbol: u8) -> f32 {
-        BASE_FREQ * SEMITONE.powi(symbol as i32)
+    pub fn new(sample_rate: u32) -> Self {
+        Self::with_profile(sample_rate, Profile::default())
     }
 
-    pub fn new(sample_rate: u32) -> Self {
-        let min_sampling_rate = (Self::calc_freq((SYMBOL_COUNT - 1) as u8) * 2.0).round() as u32;
-        if sample_rate < min_sampling_rate {
-            panic!(
-                "sample rate is too low: must be atleast {} but is {}",
-                min_sampling_rate, sample_rate
-            );
+    pub fn with_profile(sample_rate: u32, profile: Profile) -> Self {
+        Self::with_processing_rate(sample_rate, sample_rate, profile)
+    }
+
+    // Audio with `sample_rate` is converted to `processing_rate` before it's processed by the
+    // receiver. A lower processing rate reduces the required CPU time.
+    pub fn with_processing_rate(sample_rate: u32, processing_rate: u32, profile: Profile) -> Self {
+        let min_sampling_rate = profile.min_sample_rate();
+        for &rate in [sample_rate, processing_rate].iter() {
+            if rate < min_sampling_rate {
+                panic!(
+                    "sample rate is too low: must be atleast {} but is {}",
+                    min_sampling_rate, rate
+                );
+            }
         }
-        let sample_buffer_len = ((sample_rate as f32) * BEEP_TIME).round() as usize;
+        let resampler = if sample_rate != processing_rate {
+            Some(Resampler::new(sample_rate, processing_rate))
+        } else {
+            None
+        };
+        let sample_buffer_len = ((processing_rate as f32) * profile.beep_time()).round() as usize;
         assert!(sample_buffer_len > 0);
         let window_weights = {
             let mut window_weights = vec![0f32; sample_buffer_len];
@@ -81,71 +155,251 @@ impl Transceiver {
             }
             window_weights
         };
-        let mut goertzel_filters: [goertzel::Parameters; SYMBOL_COUNT] =
-            unsafe { std::mem::MaybeUninit::uninit().assume_init() };
-        for i in 0..goertzel_filters.len() {
-            goertzel_filters[i] =
-                goertzel::Parameters::new(Self::calc_freq(i as u8), sample_rate, sample_buffer_len);
+        let mut goertzel_filters =
+            [goertzel::Parameters::new(0., processing_rate, sample_buffer_len); SYMBOL_COUNT];
+        for (i, goertzel_filter) in goertzel_filters.iter_mut().enumerate() {
+            *goertzel_filter = goertzel::Parameters::new(
+                profile.frequencies()[i],
+                processing_rate,
+                sample_buffer_len,
+            );
         }
         Self {
-            sample_rate: sample_rate,
+            sample_rate,
+            processing_rate,
+            resampler,
+            level_meter: LevelMeter::new(sample_rate),
+            sample_count: 0,
             measurement_count: 0,
-            remaining_samples: (sample_rate as f32) * BEEP_TIME / (MEASUREMENTS_PER_SYMBOL as f32),
+            remaining_samples: (processing_rate as f32) * profile.beep_time()
+                / (MEASUREMENTS_PER_SYMBOL as f32),
             sample_buffer: vec![0.; sample_buffer_len],
             sample_buffer_pos: 0,
-            window_weights: window_weights,
+            window_weights,
             rs_decoder: reed_solomon::Decoder::new(ECC_LEN),
-            rs_encoder: reed_solomon::Encoder::new(ECC_LEN),
-            goertzel_filters: goertzel_filters,
-            packets: [Default::default(); MEASUREMENTS_PER_SYMBOL * PACKET_LEN],
+            goertzel_filters,
+            interleave_depth: 1,
+            packets: Self::new_packets(1),
             packets_pos: 0,
             valid_packet: false,
-            valid_packet_payload: Default::default(),
+            valid_packet_payloads: Vec::new(),
             valid_packet_quality: Default::default(),
             first_valid_packet_age: Default::default(),
+            duplicate_window: 0,
+            pending_packets: VecDeque::new(),
+            transmitter: TransmitStream::new(sample_rate, profile.clone()),
+            duplex_mode: Default::default(),
+            echo_canceller: None,
+            profile,
         }
     }
 
+    pub fn profile(&self) -> &Profile {
+        &self.profile
+    }
+
+    // The built-in transmitter generates the audio data for queued packets with the
+    // sample rate, the profile, the envelope and the tone gains of the Transceiver
+    pub fn transmitter(&mut self) -> &mut TransmitStream {
+        &mut self.transmitter
+    }
+
+    // Set how the receiver handles our own transmissions from the built-in transmitter
+    // (default: `DuplexMode::Full`)
+    pub fn set_duplex_mode(&mut self, mode: DuplexMode) {
+        self.duplex_mode = mode;
+    }
+
+    // Audio that is received within `tail` seconds after the end of a transmission is still
+    // treated as our own transmission, to cover the latency of the speaker and the microphone
+    // (default: `TRANSMIT_TAIL_TIME`). See method `set_duplex_mode`
+    pub fn set_transmit_tail(&mut self, tail: f32) {
+        self.transmitter.set_tail(tail);
+    }
+
+    // Subtract the echo of the built-in transmitter from the input with an adaptive filter.
+    // This allows to receive while transmitting. `echo_time` is the longest delay of the echo,
+    // including the latency of the speaker and the microphone. The samples of the transmitter
+    // are aligned to the input by their position in the streams. The filter runs at the full
+    // sample rate and has `echo_time * sample_rate` taps (e.g. 2205 at `0.05` seconds and
+    // 44.1 kHz), each costs two multiply-adds per input sample. Keep `echo_time` short on slow
+    // devices.
+    // Set to `0.` to disable (default).
+    pub fn set_echo_cancellation(&mut self, echo_time: f32) {
+        assert!(echo_time >= 0., "echo time must not be negative");
+        let len = ((self.sample_rate as f32) * echo_time).round() as usize;
+        self.echo_canceller = if len > 0 {
+            Some(EchoCanceller::new(len))
+        } else {
+            None
+        };
+        self.transmitter.set_recording(len);
+    }
+
+    // Set the amplitude envelope of generated beeps (default: `Envelope::Linear`)
+    pub fn set_envelope(&mut self, envelope: Envelope) {
+        self.transmitter.set_envelope(envelope);
+    }
+
+    // Set the gain of generated beeps for each symbol, e.g. to compensate for the frequency
+    // response of speakers and microphones (default: `1.`)
+    pub fn set_tone_gains(&mut self, tone_gains: [f32; SYMBOL_COUNT]) {
+        self.transmitter.set_tone_gains(tone_gains);
+    }
+
+    // Get the RMS, peak and fraction of clipped samples of the input audio
+    pub fn input_level(&self) -> InputLevel {
+        self.level_meter.level()
+    }
+
+    // Get the next warning about clipping or silence in the input audio.
+    // A warning is emitted once, when the condition persists for `LEVEL_WARNING_TIME` seconds.
+    // A warning that is still queued is not repeated.
+    pub fn poll_level_warning(&mut self) -> Option<LevelWarning> {
+        self.level_meter.poll_warning()
+    }
+
+    fn new_packets(interleave_depth: usize) -> Vec<Packet> {
+        let packet = Packet {
+            data: vec![0; PACKET_LEN * interleave_depth],
+            data_pos: 0,
+            signal_quality: 0.,
+        };
+        vec![packet; MEASUREMENTS_PER_SYMBOL * PACKET_LEN * interleave_depth]
+    }
+
+    // Interleave the symbols of `depth` consecutive packets, to spread burst errors over
+    // multiple packets. A burst of up to `depth * ECC_LEN / 2` consecutive symbols can be
+    // corrected. The sender and the receiver must use the same depth (default: 1).
+    // See method `send_interleaved`
+    pub fn set_interleave_depth(&mut self, depth: usize) {
+        assert!(depth > 0, "interleave depth must be atleast 1");
+        self.interleave_depth = depth;
+        self.transmitter.set_interleave_depth(depth);
+        self.packets = Self::new_packets(depth);
+        self.packets_pos = 0;
+        self.valid_packet = false;
+    }
+
+    // Suppress identical payloads that are received within `window` seconds after the first
+    // copy. Only the copy of the highest quality is returned and its metadata contains the
+    // number of received copies. The payload is returned after the window has passed.
+    // Set to `0.` to disable (default).
+    pub fn set_duplicate_window(&mut self, window: f32) {
+        assert!(window >= 0., "duplicate window must not be negative");
+        self.duplicate_window = ((self.sample_rate as f32) * window).round() as u64;
+    }
+
     // Get the beep frequencies for the packet containing `payload`.
-    // The timmings `BEEP_TIME`, `ATTACK_TIME` and `RELEASE_TIME` should be used for the beeps.
+    // The timmings `beep_time`, `attack_time` and `release_time` of the profile should be used
+    // for the beeps.
     // See method `generate_beep`
     pub fn send(&self, payload: &[u8; PAYLOAD_LEN]) -> [f32; PACKET_LEN] {
-        if let Some(v) = payload.iter().find(|v| (**v as usize) > SYMBOL_COUNT) {
-            panic!(
-                "symbol out of bounds: must be smaller than {} but is {}",
-                SYMBOL_COUNT, v
-            );
-        }
-        let mut data: [u8; START_SYMBOLS_LEN + PAYLOAD_LEN] = Default::default();
-        data[..START_SYMBOLS_LEN].copy_from_slice(&START_SYMBOLS);
-        data[START_SYMBOLS_LEN..].copy_from_slice(payload);
-        let encoded_data = self.rs_encoder.encode(&data);
+        assert_eq!(
+            self.interleave_depth, 1,
+            "interleaving is enabled: use method `send_interleaved`"
+        );
         let mut frequencies: [f32; PACKET_LEN] = Default::default();
-        debug_assert_eq!(encoded_data.len(), frequencies.len());
-        for (i, symbol) in encoded_data.iter().enumerate() {
-            frequencies[i] = Self::calc_freq(*symbol);
-        }
+        frequencies.copy_from_slice(&self.transmitter.frequencies(&[*payload]));
         frequencies
     }
 
+    // Get the beep frequencies for the frame containing the interleaved packets with `payloads`.
+    // The number of payloads must be equal to the interleave depth.
+    // See method `set_interleave_depth`
+    pub fn send_interleaved(&self, payloads: &[[u8; PAYLOAD_LEN]]) -> Vec<f32> {
+        self.transmitter.frequencies(payloads)
+    }
+
     // Commit an audio sample to the receiver
     pub fn push_sample(&mut self, sample: f32) -> Option<[u8; PAYLOAD_LEN]> {
+        self.push_sample_with_metadata(sample)
+            .map(|(payload, _)| payload)
+    }
+
+    // Like `push_sample`, but additionally returns information about the received packet
+    pub fn push_sample_with_metadata(
+        &mut self,
+        sample: f32,
+    ) -> Option<([u8; PAYLOAD_LEN], PacketMetadata)> {
+        self.sample_count += 1;
+        self.level_meter.push_sample(sample);
+        let sample = match self.echo_canceller.as_mut() {
+            Some(echo_canceller) => {
+                let reference = self.transmitter.pop_recorded_sample().unwrap_or(0.);
+                echo_canceller.process(reference, sample)
+            }
+            None => sample,
+        };
+        let sample = if self.duplex_mode == DuplexMode::Mute && self.transmitter.is_active() {
+            0.
+        } else {
+            sample
+        };
+        if let Some(resampler) = self.resampler.as_mut() {
+            resampler.push(sample);
+            while let Some(sample) = self.resampler.as_mut().and_then(Resampler::pop) {
+                self.receive_frame(sample);
+            }
+        } else {
+            self.receive_frame(sample);
+        }
+        // Return the oldest packet after its duplicate window has passed
+        match self.pending_packets.front() {
+            Some(pending_packet)
+                if self.sample_count - pending_packet.first_received_at
+                    >= self.duplicate_window =>
+            {
+                let pending_packet = self.pending_packets.pop_front().unwrap();
+                Some((pending_packet.payload, pending_packet.metadata))
+            }
+            _ => None,
+        }
+    }
+
+    fn queue_packet(&mut self, payload: [u8; PAYLOAD_LEN], metadata: PacketMetadata) {
+        if self.duplex_mode == DuplexMode::DropOwn && self.transmitter.is_own_payload(&payload) {
+            return;
+        }
+        let (sample_count, duplicate_window) = (self.sample_count, self.duplicate_window);
+        let pending_packet = self.pending_packets.iter_mut().find(|pending_packet| {
+            pending_packet.payload == payload
+                && sample_count - pending_packet.first_received_at < duplicate_window
+        });
+        if let Some(pending_packet) = pending_packet {
+            let repeat_count = pending_packet.metadata.repeat_count + 1;
+            if pending_packet.metadata.quality() < metadata.quality() {
+                pending_packet.metadata = metadata;
+            }
+            pending_packet.metadata.repeat_count = repeat_count;
+        } else {
+            self.pending_packets.push_back(PendingPacket {
+                payload,
+                metadata,
+                first_received_at: self.sample_count,
+            });
+        }
+    }
+
+    fn receive_frame(&mut self, sample: f32) {
         // Push new sample to ring buffer
         self.sample_buffer[self.sample_buffer_pos] = sample;
         self.sample_buffer_pos = mod_short!(self.sample_buffer_pos + 1, self.sample_buffer.len());
         self.remaining_samples -= 1.;
         if self.remaining_samples > 0. {
-            return None;
+            return;
         }
-        self.remaining_samples +=
-            (self.sample_rate as f32) * BEEP_TIME / (MEASUREMENTS_PER_SYMBOL as f32);
+        self.remaining_samples += (self.processing_rate as f32) * self.profile.beep_time()
+            / (MEASUREMENTS_PER_SYMBOL as f32);
         self.measurement_count += 1;
 
         // Decode symbol
-        let mut goertzel_partials: [goertzel::Partial; SYMBOL_COUNT] =
-            unsafe { std::mem::MaybeUninit::uninit().assume_init() };
-        for (i, goertzel_filter) in self.goertzel_filters.iter().enumerate() {
-            goertzel_partials[i] = goertzel_filter.start();
+        let mut goertzel_partials = [self.goertzel_filters[0].start(); SYMBOL_COUNT];
+        for (goertzel_partial, goertzel_filter) in goertzel_partials
+            .iter_mut()
+            .zip(self.goertzel_filters.iter())
+        {
+            *goertzel_partial = goertzel_filter.start();
         }
         for i in 0..self.sample_buffer.len() {
             let j = mod_short!(self.sample_buffer_pos + i, self.sample_buffer.len());
@@ -171,12 +425,13 @@ impl Transceiver {
         let next_symbol_snr = next_symbol_magnitude / next_symbol_noise;
 
         // Reset packet
-        self.packets[self.packets_pos] = Default::default();
-        for i in 0..PACKET_LEN {
-            let packet = &mut self.packets[mod_short!(
-                self.packets_pos + i * MEASUREMENTS_PER_SYMBOL,
-                self.packets.len()
-            )];
+        self.packets[self.packets_pos].data_pos = 0;
+        self.packets[self.packets_pos].signal_quality = 0.;
+        let frame_len = PACKET_LEN * self.interleave_depth;
+        let packets_len = self.packets.len();
+        for i in 0..frame_len {
+            let packet = &mut self.packets
+                [mod_short!(self.packets_pos + i * MEASUREMENTS_PER_SYMBOL, packets_len)];
             packet.data[packet.data_pos] = next_symbol;
             packet.data_pos += 1;
             packet.signal_quality += next_symbol_snr; // average SNR of symbols
@@ -189,41 +444,57 @@ impl Transceiver {
         self.packets_pos = mod_short!(self.packets_pos + 1, self.packets.len());
 
         // Process the completed packet unless we are just starting up or the packet was dropped
-        if completed_packet.data_pos == PACKET_LEN {
-            if let Ok(corrected_data) = self.rs_decoder.correct(&completed_packet.data, None) {
+        if completed_packet.data_pos == frame_len {
+            let mut frame_payloads = Vec::with_capacity(self.interleave_depth);
+            let mut correct_symbols = 0;
+            for i in 0..self.interleave_depth {
+                // Deinterleave codeword
+                let mut codeword: [u8; PACKET_LEN] = Default::default();
+                for (j, symbol) in codeword.iter_mut().enumerate() {
+                    *symbol = completed_packet.data[j * self.interleave_depth + i];
+                }
+                let corrected_data = match self.rs_decoder.correct(&codeword, None) {
+                    Ok(corrected_data) => corrected_data,
+                    Err(_) => break,
+                };
                 let corrected_data = corrected_data.data();
                 let start_symbols_ok = corrected_data[..START_SYMBOLS_LEN] == START_SYMBOLS;
-                if start_symbols_ok {
-                    let mut correct_symbols = 0;
-                    for (i, &c) in corrected_data.iter().enumerate() {
-                        if completed_packet.data[i] == c {
-                            correct_symbols += 1;
-                        }
+                if !start_symbols_ok {
+                    break;
+                }
+                let mut codeword_correct_symbols = 0;
+                for (i, &c) in corrected_data.iter().enumerate() {
+                    if codeword[i] == c {
+                        codeword_correct_symbols += 1;
                     }
-                    let packet_quality = (correct_symbols, completed_packet.signal_quality);
-                    // Replace old valid packet if new valid packet is of higher quality
-                    if !self.valid_packet || self.valid_packet_quality < packet_quality {
-                        self.valid_packet_payload
-                            .copy_from_slice(&corrected_data[START_SYMBOLS_LEN..][..PAYLOAD_LEN]);
-                        self.valid_packet_quality = packet_quality;
+                }
+                correct_symbols += codeword_correct_symbols;
+                let mut payload: [u8; PAYLOAD_LEN] = Default::default();
+                payload.copy_from_slice(&corrected_data[START_SYMBOLS_LEN..][..PAYLOAD_LEN]);
+                frame_payloads.push((payload, codeword_correct_symbols));
+            }
+            // All packets of the frame must be valid
+            if frame_payloads.len() == self.interleave_depth {
+                let packet_quality = (correct_symbols, completed_packet.signal_quality);
+                // Replace old valid packet if new valid packet is of higher quality
+                if !self.valid_packet || self.valid_packet_quality < packet_quality {
+                    self.valid_packet_payloads = frame_payloads;
+                    self.valid_packet_quality = packet_quality;
+                }
+                if !self.valid_packet {
+                    self.valid_packet = true;
+                    self.first_valid_packet_age = 0;
+                    // Drop all old partial packets that are not capturing the same
+                    // packet overlapping. The remaining packets are only missing
+                    // the last symbol.
+                    for i in MEASUREMENTS_PER_SYMBOL..self.packets.len() {
+                        let packet_pos = mod_short!(completed_packet_pos + i, self.packets.len());
+                        self.packets[packet_pos].data_pos = 0;
                     }
-                    if !self.valid_packet {
-                        self.valid_packet = true;
-                        self.first_valid_packet_age = 0;
-                        // Drop all old partial packets that are not capturing the same
-                        // packet overlapping. The remaining packets are only missing
-                        // the last symbol.
-                        for i in MEASUREMENTS_PER_SYMBOL..self.packets.len() {
-                            let packet_pos =
-                                mod_short!(completed_packet_pos + i, self.packets.len());
-                            self.packets[packet_pos].data_pos = 0;
-                        }
-                        #[cfg(debug_assertions)]
-                        for i in 1..MEASUREMENTS_PER_SYMBOL {
-                            let packet_pos =
-                                mod_short!(completed_packet_pos + i, self.packets.len());
-                            assert!(self.packets[packet_pos].data_pos == PACKET_LEN - 1);
-                        }
+                    #[cfg(debug_assertions)]
+                    for i in 1..MEASUREMENTS_PER_SYMBOL {
+                        let packet_pos = mod_short!(completed_packet_pos + i, self.packets.len());
+                        assert!(self.packets[packet_pos].data_pos == frame_len - 1);
                     }
                 }
             }
@@ -235,24 +506,31 @@ impl Transceiver {
             self.first_valid_packet_age += 1;
             if self.first_valid_packet_age == MEASUREMENTS_PER_SYMBOL {
                 self.valid_packet = false;
-                return Some(self.valid_packet_payload);
+                let signal_quality = self.valid_packet_quality.1 / (frame_len as f32);
+                for (payload, correct_symbols) in std::mem::take(&mut self.valid_packet_payloads) {
+                    let metadata = PacketMetadata {
+                        correct_symbols,
+                        signal_quality,
+                        repeat_count: 1,
+                        received_at: self.sample_count,
+                        input_level: self.level_meter.level(),
+                    };
+                    self.queue_packet(payload, metadata);
+                }
             }
         }
-        return None;
     }
 
     // Generate audio data for a beep with the specified `frequency`
-    // The timings from the constants `BEEP_TIME`, `ATTACK_TIME`, `RELEASE_TIME` and
-    // the `sampling_rate` of the Transceiver are used.
+    // The timings `beep_time`, `attack_time`, `release_time` of the profile,
+    // the envelope, the tone gains and the `sampling_rate` of the Transceiver are used.
     pub fn generate_beep(&self, frequency: f32) -> Vec<f32> {
-        let samples_len = (BEEP_TIME * (self.sample_rate as f32)).round() as usize;
-        (0..samples_len)
-            .map(|i| {
-                let t = BEEP_TIME / (samples_len as f32) * (i as f32);
-                let window = (t / ATTACK_TIME).min(1.) * ((BEEP_TIME - t) / RELEASE_TIME).min(1.);
-                (t * frequency * 2. * PI).sin() * window
-            })
-            .collect()
+        self.generate_beep_with_sample_rate(frequency, self.sample_rate)
+    }
+
+    // Like `generate_beep`, but the audio data is generated with `sample_rate`
+    pub fn generate_beep_with_sample_rate(&self, frequency: f32, sample_rate: u32) -> Vec<f32> {
+        self.transmitter.generate_beep(frequency, sample_rate)
     }
 }
 
@@ -261,12 +539,14 @@ pub mod test_utils {
     use super::*;
     use rand::prelude::*;
 
+    pub mod channel;
+
     // Generate deterministic random payload based on `seed`
     pub fn rand_payload(seed: usize) -> [u8; PAYLOAD_LEN] {
         let mut rng = SmallRng::seed_from_u64(seed as u64);
         let mut payload = [0u8; PAYLOAD_LEN];
-        for i in 0..payload.len() {
-            payload[i] = rng.gen_range(0, SYMBOL_COUNT) as u8;
+        for symbol in payload.iter_mut() {
+            *symbol = rng.gen_range(0, SYMBOL_COUNT) as u8;
         }
         payload
     }
@@ -275,16 +555,51 @@ pub mod test_utils {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::prelude::*;
     use test_utils::*;
 
+    const SAMPLE_RATE: u32 = 44100;
+
     // Test coherency of global constants
     #[test]
     fn test_constants() {
         assert_eq!(2usize.pow(SYMBOL_BITS as u32), SYMBOL_COUNT);
-        assert_eq!(SYMBOL_MNEMONICS.len(), SYMBOL_COUNT);
         assert_eq!(format!("{:?}", GF {}), format!("GF2_{}", SYMBOL_BITS));
     }
 
+    // Test that clipped and quiet input raises warnings
+    #[test]
+    fn test_level_warnings() {
+        let mut transceiver = Transceiver::new(SAMPLE_RATE);
+        let warning_len = ((SAMPLE_RATE as f32) * LEVEL_WARNING_TIME * 1.5) as usize;
+        for i in 0..warning_len {
+            transceiver.push_sample(if i % 2 == 0 { 1. } else { -1. });
+        }
+        assert!(transceiver.input_level().clipped > 0.5);
+        assert_eq!(
+            transceiver.poll_level_warning(),
+            Some(LevelWarning::Clipping)
+        );
+        assert_eq!(transceiver.poll_level_warning(), None);
+        for _ in 0..warning_len {
+            transceiver.push_sample(1e-5);
+        }
+        assert_eq!(
+            transceiver.poll_level_warning(),
+            Some(LevelWarning::Silence)
+        );
+        assert_eq!(transceiver.poll_level_warning(), None);
+    }
+
+    // Test that symbols outside of the alphabet are rejected
+    #[test]
+    #[should_panic(expected = "symbol out of bounds")]
+    fn test_symbol_out_of_bounds() {
+        let mut payload = [0; PAYLOAD_LEN];
+        payload[0] = SYMBOL_COUNT as u8;
+        Transceiver::new(SAMPLE_RATE).send(&payload);
+    }
+
     // Test sending and subsequent receiving of multiple successive packets
     #[test]
     fn test_send_and_receive() {
@@ -310,4 +625,512 @@ mod tests {
         }
         assert_eq!(received_count, SEND_COUNT);
     }
+
+    // Apply a biquad filter (see "Cookbook formulae for audio EQ biquad filter coefficients")
+    fn biquad(samples: &mut [f32], sample_rate: u32, frequency: f32, high_pass: bool) {
+        let w0 = 2. * PI * frequency / (sample_rate as f32);
+        let alpha = w0.sin() / (2. * f32::consts::FRAC_1_SQRT_2);
+        let cos_w0 = w0.cos();
+        let (b0, b1) = if high_pass {
+            ((1. + cos_w0) / 2., -(1. + cos_w0))
+        } else {
+            ((1. - cos_w0) / 2., 1. - cos_w0)
+        };
+        let (a0, a1, a2) = (1. + alpha, -2. * cos_w0, 1. - alpha);
+        let (mut x1, mut x2, mut y1, mut y2) = (0., 0., 0., 0.);
+        for sample in samples.iter_mut() {
+            let y = (b0 * *sample + b1 * x1 + b0 * x2 - a1 * y1 - a2 * y2) / a0;
+            x2 = x1;
+            x1 = *sample;
+            y2 = y1;
+            y1 = y;
+            *sample = y;
+        }
+    }
+
+    // Test sending and receiving with the telephone profile through the telephone band
+    #[test]
+    fn test_telephone_band() {
+        const SAMPLE_RATE: u32 = 8000;
+        const SEND_COUNT: usize = 3;
+        let mut transceiver = Transceiver::with_profile(SAMPLE_RATE, Profile::telephone());
+        let mut samples = Vec::new();
+        for i in 0..SEND_COUNT {
+            for &frequency in transceiver.send(&rand_payload(i)).iter() {
+                samples.extend(transceiver.generate_beep(frequency));
+            }
+        }
+        samples.extend(vec![0.; SAMPLE_RATE as usize]);
+        // 4th order band-pass filter from 300 Hz to 3400 Hz
+        for _ in 0..2 {
+            biquad(&mut samples, SAMPLE_RATE, 300., true);
+            biquad(&mut samples, SAMPLE_RATE, 3400., false);
+        }
+        let received: Vec<[u8; PAYLOAD_LEN]> = samples
+            .iter()
+            .filter_map(|&sample| transceiver.push_sample(sample))
+            .collect();
+        let expected: Vec<[u8; PAYLOAD_LEN]> = (0..SEND_COUNT).map(rand_payload).collect();
+        assert_eq!(received, expected);
+    }
+
+    // Coarse model of a speech codec like GSM or Opus at 8 kHz: the telephone band, gain changes
+    // between frames of 20 ms and 8-bit µ-law quantization (G.711)
+    fn speech_codec(samples: &mut [f32], seed: u64) {
+        const SAMPLE_RATE: u32 = 8000;
+        const FRAME_TIME: f32 = 0.02;
+        const MU: f32 = 255.;
+        for _ in 0..2 {
+            biquad(samples, SAMPLE_RATE, 300., true);
+            biquad(samples, SAMPLE_RATE, 3400., false);
+        }
+        let mut rng = SmallRng::seed_from_u64(seed);
+        let frame_len = ((SAMPLE_RATE as f32) * FRAME_TIME) as usize;
+        for frame in samples.chunks_mut(frame_len) {
+            let gain = 10f32.powf(rng.gen_range(-2., 2.) / 20.);
+            for sample in frame.iter_mut() {
+                let x = (*sample * gain).clamp(-1., 1.);
+                let encoded = x.signum() * (1. + MU * x.abs()).ln() / (1. + MU).ln();
+                let quantized = (encoded * 127.).round() / 127.;
+                *sample = quantized.signum() * ((1. + MU).powf(quantized.abs()) - 1.) / MU;
+            }
+        }
+    }
+
+    // Test the telephone profile through a band-limited speech codec and a noisy channel
+    #[test]
+    fn test_telephone_codec() {
+        const SAMPLE_RATE: u32 = 8000;
+        const SEND_COUNT: usize = 5;
+        let mut transceiver = Transceiver::with_profile(SAMPLE_RATE, Profile::telephone());
+        let mut samples = Vec::new();
+        for i in 0..SEND_COUNT {
+            for &frequency in transceiver.send(&rand_payload(i)).iter() {
+                samples.extend(transceiver.generate_beep(frequency).iter().map(|x| x * 0.5));
+            }
+        }
+        samples.extend(vec![0.; SAMPLE_RATE as usize]);
+        speech_codec(&mut samples, 0);
+        let mut channel = channel::Channel::new(SAMPLE_RATE, 0)
+            .white_noise(20.)
+            .drift(50);
+        let received: Vec<[u8; PAYLOAD_LEN]> = channel
+            .process(&samples)
+            .iter()
+            .filter_map(|&sample| transceiver.push_sample(sample))
+            .collect();
+        let expected: Vec<[u8; PAYLOAD_LEN]> = (0..SEND_COUNT).map(rand_payload).collect();
+        assert_eq!(received, expected);
+    }
+
+    // Test receiving with a processing rate that differs from the sample rate
+    #[test]
+    fn test_processing_rate() {
+        const SEND_COUNT: usize = 2;
+        for &(sample_rate, processing_rate) in [(96000, 22050), (22050, 32000)].iter() {
+            let mut transceiver =
+                Transceiver::with_processing_rate(sample_rate, processing_rate, Profile::default());
+            let mut samples = Vec::new();
+            for i in 0..SEND_COUNT {
+                for &frequency in transceiver.send(&rand_payload(i)).iter() {
+                    samples.extend(transceiver.generate_beep(frequency));
+                }
+            }
+            samples.extend(vec![0.; (sample_rate as f32 * BEEP_TIME).ceil() as usize]);
+            let received: Vec<[u8; PAYLOAD_LEN]> = samples
+                .iter()
+                .filter_map(|&sample| transceiver.push_sample(sample))
+                .collect();
+            let expected: Vec<[u8; PAYLOAD_LEN]> = (0..SEND_COUNT).map(rand_payload).collect();
+            assert_eq!(received, expected);
+        }
+    }
+
+    // Get the ratio between the magnitude in the symbols `distance` away and the magnitude in
+    // `symbol`
+    fn leakage(transceiver: &Transceiver, symbol: usize, distance: usize) -> f32 {
+        let frequencies = &transceiver.profile().frequencies();
+        let beep = transceiver.generate_beep(frequencies[symbol]);
+        let magnitude = |frequency| {
+            let mut partial = goertzel::Parameters::new(frequency, SAMPLE_RATE, beep.len()).start();
+            for &sample in beep.iter() {
+                partial.push(sample);
+            }
+            partial.finish_mag()
+        };
+        // Find the highest sidelobe around the other symbols
+        let mut leakage = 0f32;
+        for &other_symbol in [symbol - distance, symbol + distance].iter() {
+            let spacing = (frequencies[other_symbol] - frequencies[symbol]).abs() / distance as f32;
+            for i in -10..=10 {
+                let frequency = frequencies[other_symbol] + spacing * 0.04 * (i as f32);
+                leakage = leakage.max(magnitude(frequency));
+            }
+        }
+        leakage / magnitude(frequencies[symbol])
+    }
+
+    // Test that smooth envelopes leak less energy into other symbols than linear ramps.
+    // The raised cosine ramps have a wider main lobe, they only leak less beyond the adjacent
+    // symbols.
+    #[test]
+    fn test_envelope_leakage() {
+        let mut transceiver = Transceiver::new(SAMPLE_RATE);
+        for &(envelope, distance) in [
+            (Envelope::RaisedCosine, 2),
+            (Envelope::Gaussian { sigma: 0.15 }, 1),
+            (Envelope::Tukey { taper: 0.5 }, 1),
+            (Envelope::Tukey { taper: 1. }, 1),
+        ]
+        .iter()
+        {
+            for symbol in distance..SYMBOL_COUNT - distance {
+                transceiver.set_envelope(Envelope::Linear);
+                let linear_leakage = leakage(&transceiver, symbol, distance);
+                transceiver.set_envelope(envelope);
+                let leakage = leakage(&transceiver, symbol, distance);
+                assert!(
+                    leakage < linear_leakage,
+                    "symbol {}: leakage of {:?} is {} but linear is {}",
+                    symbol,
+                    envelope,
+                    leakage,
+                    linear_leakage
+                );
+            }
+        }
+    }
+
+    // Test that envelopes with invalid parameters are rejected
+    #[test]
+    fn test_invalid_envelopes() {
+        let sigma_error = "sigma of the envelope must be positive";
+        let taper_error = "taper of the envelope must be in the range 0 to 1";
+        let cases = [
+            // A Gaussian without width divides by zero
+            (Envelope::Gaussian { sigma: 0. }, Some(sigma_error)),
+            (Envelope::Gaussian { sigma: -0.1 }, Some(sigma_error)),
+            (Envelope::Gaussian { sigma: f32::NAN }, Some(sigma_error)),
+            // The tapered parts can't be longer than the beep
+            (Envelope::Tukey { taper: 1.5 }, Some(taper_error)),
+            (Envelope::Tukey { taper: -0.5 }, Some(taper_error)),
+            (Envelope::Tukey { taper: f32::NAN }, Some(taper_error)),
+            // The limits are a rectangular window and a Hann window
+            (Envelope::Tukey { taper: 0. }, None),
+            (Envelope::Tukey { taper: 1. }, None),
+        ];
+        for &(envelope, expected) in cases.iter() {
+            let result = std::panic::catch_unwind(|| {
+                Transceiver::new(SAMPLE_RATE).set_envelope(envelope);
+            });
+            let message = result.err().map(|err| match err.downcast::<String>() {
+                Ok(message) => *message,
+                Err(err) => err.downcast_ref::<&str>().unwrap().to_string(),
+            });
+            assert_eq!(message.as_deref(), expected, "{:?}", envelope);
+        }
+    }
+
+    // Test that tone gains are applied to the symbol with the closest frequency
+    #[test]
+    fn test_tone_gains() {
+        let mut transceiver = Transceiver::new(SAMPLE_RATE);
+        let mut tone_gains = [1.; SYMBOL_COUNT];
+        tone_gains[3] = 0.25;
+        transceiver.set_tone_gains(tone_gains);
+        for (i, &frequency) in transceiver.profile().frequencies().iter().enumerate() {
+            let beep = transceiver.generate_beep(frequency * 1.01);
+            let peak = beep.iter().cloned().fold(0., f32::max);
+            assert!((peak - tone_gains[i]).abs() < 0.01);
+        }
+    }
+
+    // Replace `len` beeps starting at beep `start` with loud white noise
+    fn add_burst(samples: &mut [f32], start: usize, len: usize, seed: u64) {
+        let beep_len = ((SAMPLE_RATE as f32) * BEEP_TIME).round() as usize;
+        let mut rng = SmallRng::seed_from_u64(seed);
+        for sample in samples[start * beep_len..(start + len) * beep_len].iter_mut() {
+            *sample = rng.gen_range(-2., 2.);
+        }
+    }
+
+    fn receive_all(transceiver: &mut Transceiver, samples: &[f32]) -> Vec<[u8; PAYLOAD_LEN]> {
+        let silence = vec![0.; ((SAMPLE_RATE as f32) * BEEP_TIME).ceil() as usize];
+        samples
+            .iter()
+            .chain(silence.iter())
+            .filter_map(|&sample| transceiver.push_sample(sample))
+            .collect()
+    }
+
+    // Test that interleaving corrects bursts that can't be corrected in a single packet
+    #[test]
+    fn test_interleaved_burst() {
+        const DEPTH: usize = 3;
+        const BURST_LEN: usize = 5;
+        let mut transceiver = Transceiver::new(SAMPLE_RATE);
+        let mut samples: Vec<f32> = Vec::new();
+        for &frequency in transceiver.send(&rand_payload(0)).iter() {
+            samples.extend(transceiver.generate_beep(frequency));
+        }
+        add_burst(&mut samples, 4, BURST_LEN, 0);
+        assert!(receive_all(&mut transceiver, &samples).is_empty());
+
+        let payloads: Vec<[u8; PAYLOAD_LEN]> = (0..DEPTH).map(rand_payload).collect();
+        for (seed, &burst_start) in [0, 7, DEPTH * PACKET_LEN - BURST_LEN].iter().enumerate() {
+            let mut transceiver = Transceiver::new(SAMPLE_RATE);
+            transceiver.set_interleave_depth(DEPTH);
+            let mut samples: Vec<f32> = Vec::new();
+            for &frequency in transceiver.send_interleaved(&payloads).iter() {
+                samples.extend(transceiver.generate_beep(frequency));
+            }
+            add_burst(&mut samples, burst_start, BURST_LEN, seed as u64);
+            assert_eq!(receive_all(&mut transceiver, &samples), payloads);
+        }
+    }
+
+    // Test that repeated transmissions of the same packet are only returned once
+    #[test]
+    fn test_duplicate_suppression() {
+        const REPEAT_COUNT: usize = 3;
+        const SEND_COUNT: usize = 2;
+        let mut transceiver = Transceiver::new(SAMPLE_RATE);
+        let packet_time = BEEP_TIME * (PACKET_LEN as f32);
+        transceiver.set_duplicate_window(packet_time * (REPEAT_COUNT as f32));
+        let mut received = Vec::new();
+        let mut push_sample_and_receive = |transceiver: &mut Transceiver, sample| {
+            if let Some(packet) = transceiver.push_sample_with_metadata(sample) {
+                received.push(packet);
+            }
+        };
+        for i in 0..SEND_COUNT {
+            for _ in 0..REPEAT_COUNT {
+                for &frequency in transceiver.send(&rand_payload(i)).iter() {
+                    for &sample in transceiver.generate_beep(frequency).iter() {
+                        push_sample_and_receive(&mut transceiver, sample);
+                    }
+                }
+            }
+        }
+        for _ in 0..((SAMPLE_RATE as f32) * packet_time * (REPEAT_COUNT as f32)).ceil() as u32 {
+            push_sample_and_receive(&mut transceiver, 0.);
+        }
+        assert_eq!(received.len(), SEND_COUNT);
+        for (i, (payload, metadata)) in received.iter().enumerate() {
+            assert_eq!(*payload, rand_payload(i));
+            assert_eq!(metadata.repeat_count, REPEAT_COUNT);
+            assert_eq!(metadata.correct_symbols, START_SYMBOLS_LEN + PAYLOAD_LEN);
+        }
+    }
+
+    // Test handling of our own transmissions, while another device transmits after them
+    #[test]
+    fn test_duplex_modes() {
+        let packet_len = PACKET_LEN * ((SAMPLE_RATE as f32) * BEEP_TIME).round() as usize;
+        let other_start = packet_len + ((SAMPLE_RATE as f32) * TRANSMIT_TAIL_TIME * 2.) as usize;
+        let mut other_samples = vec![0.; other_start];
+        let other = Transceiver::new(SAMPLE_RATE);
+        for &frequency in other.send(&rand_payload(1)).iter() {
+            other_samples.extend(other.generate_beep(frequency));
+        }
+        other_samples.extend(vec![0.; SAMPLE_RATE as usize]);
+        for &(mode, expected) in [
+            (DuplexMode::Full, &[0, 1][..]),
+            (DuplexMode::Mute, &[1][..]),
+            (DuplexMode::DropOwn, &[1][..]),
+        ]
+        .iter()
+        {
+            let mut transceiver = Transceiver::new(SAMPLE_RATE);
+            transceiver.set_duplex_mode(mode);
+            transceiver.transmitter().enqueue(&rand_payload(0));
+            let mut received = Vec::new();
+            for &other_sample in other_samples.iter() {
+                let sample = transceiver.transmitter().next().unwrap() + other_sample;
+                received.extend(transceiver.push_sample(sample));
+            }
+            let expected: Vec<[u8; PAYLOAD_LEN]> =
+                expected.iter().map(|&i| rand_payload(i)).collect();
+            assert_eq!(received, expected, "{:?}", mode);
+        }
+    }
+
+    // Test reception of another device, while the echo of our own transmission is received
+    #[test]
+    fn test_echo_cancellation() {
+        const ECHO_DELAY: usize = 100;
+        let packet_len = PACKET_LEN * ((SAMPLE_RATE as f32) * BEEP_TIME).round() as usize;
+        let mut other_samples = vec![0.; packet_len * 3 / 2];
+        let other = Transceiver::new(SAMPLE_RATE);
+        for &frequency in other.send(&rand_payload(1)).iter() {
+            other_samples.extend(other.generate_beep(frequency).iter().map(|x| x * 0.3));
+        }
+        other_samples.extend(vec![0.; SAMPLE_RATE as usize]);
+        // Without echo cancellation the echo covers the other device
+        for &echo_time in [0., 0.01].iter() {
+            let mut transceiver = Transceiver::new(SAMPLE_RATE);
+            transceiver.set_echo_cancellation(echo_time);
+            for _ in 0..3 {
+                transceiver.transmitter().enqueue(&rand_payload(0));
+            }
+            let mut transmitted = Vec::new();
+            let mut received = Vec::new();
+            for &other_sample in other_samples.iter() {
+                transmitted.push(transceiver.transmitter().next().unwrap());
+                let echo = match transmitted.len().checked_sub(ECHO_DELAY + 1) {
+                    Some(i) => 0.8 * transmitted[i] + 0.2 * transmitted[i.saturating_sub(30)],
+                    None => 0.,
+                };
+                received.extend(transceiver.push_sample(echo + other_sample));
+            }
+            assert_eq!(
+                received.contains(&rand_payload(1)),
+                echo_time > 0.,
+                "echo time {}",
+                echo_time
+            );
+        }
+    }
+
+    // Test reception through a simulated acoustic channel
+    #[test]
+    fn test_channel() {
+        const SEND_COUNT: usize = 5;
+        let transceiver = Transceiver::new(SAMPLE_RATE);
+        let mut samples = Vec::new();
+        for i in 0..SEND_COUNT {
+            for &frequency in transceiver.send(&rand_payload(i)).iter() {
+                samples.extend(transceiver.generate_beep(frequency));
+            }
+            samples.extend(vec![0.; SAMPLE_RATE as usize / 2]);
+        }
+        let mut channel = channel::Channel::new(SAMPLE_RATE, 0)
+            .tilt(-6.)
+            .reverb(0.3, 0.3)
+            .drift(100)
+            .pink_noise(10.)
+            .speech(10.);
+        let mut transceiver = Transceiver::new(SAMPLE_RATE);
+        let received: Vec<[u8; PAYLOAD_LEN]> = channel
+            .process(&samples)
+            .iter()
+            .filter_map(|&sample| transceiver.push_sample(sample))
+            .collect();
+        let expected: Vec<[u8; PAYLOAD_LEN]> = (0..SEND_COUNT).map(rand_payload).collect();
+        assert_eq!(received, expected);
+    }
+
+    // Estimated false positives per hour of noise, see README.md
+    const FALSE_POSITIVES_PER_HOUR: f64 = 1.7;
+    const FALSE_POSITIVE_SAMPLE_RATE: u32 = 22050;
+
+    // Check that the number of packets received from `duration` seconds of audio, that is
+    // generated in chunks, stays within the bound
+    fn check_false_positives(
+        source: &str,
+        duration: f32,
+        mut generate: impl FnMut(usize) -> Vec<f32>,
+    ) {
+        let mut transceiver = Transceiver::new(FALSE_POSITIVE_SAMPLE_RATE);
+        let mut count = 0;
+        let chunk_len = FALSE_POSITIVE_SAMPLE_RATE as usize * 10;
+        let chunks = ((FALSE_POSITIVE_SAMPLE_RATE as f32) * duration) as usize / chunk_len;
+        for _ in 0..chunks {
+            for &sample in generate(chunk_len).iter() {
+                if transceiver.push_sample(sample).is_some() {
+                    count += 1;
+                }
+            }
+        }
+        let bound = false_positive_bound(duration);
+        assert!(
+            count <= bound,
+            "{}: {} false positives in {} seconds (bound: {})",
+            source,
+            count,
+            duration,
+            bound
+        );
+    }
+
+    // Upper bound for the number of false positives in `duration` seconds, that is exceeded
+    // with a probability of less than 1e-6 (Poisson distribution)
+    fn false_positive_bound(duration: f32) -> usize {
+        let expected = FALSE_POSITIVES_PER_HOUR * (duration as f64) / 3600.;
+        let mut probability = (-expected).exp();
+        let mut cumulative = probability;
+        let mut bound = 0;
+        while 1. - cumulative > 1e-6 {
+            bound += 1;
+            probability *= expected / (bound as f64);
+            cumulative += probability;
+        }
+        bound
+    }
+
+    // Random chords of tones, that are often at the frequencies of the symbols
+    fn tone_mixture(rng: &mut SmallRng, profile: &Profile, len: usize) -> Vec<f32> {
+        let sample_rate = FALSE_POSITIVE_SAMPLE_RATE as f32;
+        let mut samples = vec![0.; len];
+        let mut i = 0;
+        while i < len {
+            let note_len = (sample_rate * rng.gen_range(0.05, 0.3)) as usize;
+            let frequencies: Vec<f32> = (0..rng.gen_range(1, 5))
+                .map(|_| {
+                    if rng.gen::<bool>() {
+                        profile.frequencies()[rng.gen_range(0, SYMBOL_COUNT)]
+                    } else {
+                        rng.gen_range(200., 8000.)
+                    }
+                })
+                .collect();
+            for j in 0..note_len.min(len - i) {
+                let t = (j as f32) / sample_rate;
+                samples[i + j] = frequencies
+                    .iter()
+                    .map(|f| (2. * f32::consts::PI * f * t).sin() * 0.2)
+                    .sum();
+            }
+            i += note_len;
+        }
+        samples
+    }
+
+    fn false_positives_in_noise(duration: f32, pink: bool) {
+        let mut channel = channel::Channel::new(FALSE_POSITIVE_SAMPLE_RATE, 0);
+        let source = if pink { "pink noise" } else { "white noise" };
+        check_false_positives(source, duration, |len| {
+            channel
+                .generate_noise(len, pink)
+                .iter()
+                .map(|x| x * 0.1)
+                .collect()
+        });
+    }
+
+    fn false_positives_in_tones(duration: f32) {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let profile = Profile::default();
+        let mut channel = channel::Channel::new(FALSE_POSITIVE_SAMPLE_RATE, 0).pink_noise(20.);
+        check_false_positives("tone mixtures", duration, |len| {
+            channel.process(&tone_mixture(&mut rng, &profile, len))
+        });
+    }
+
+    // Test that random packets in noise and tone mixtures are rare
+    #[test]
+    fn test_false_positives() {
+        false_positives_in_noise(180., false);
+        false_positives_in_tones(180.);
+    }
+
+    // Like `test_false_positives` with hours of audio, run with
+    // `cargo test --release -- --ignored --nocapture` to show the counts
+    #[test]
+    #[ignore]
+    fn test_false_positives_long() {
+        false_positives_in_noise(3600., false);
+        false_positives_in_noise(3600., true);
+        false_positives_in_tones(3600.);
+    }
 }
@@ -0,0 +1,1616 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use super::{BEEP_TIME, PACKET_LEN, PAYLOAD_LEN, SYMBOL_BITS, SYMBOL_COUNT};
+
+mod auth;
+mod cipher;
+mod fountain;
+mod record;
+mod text;
+
+pub use auth::AuthError;
+use auth::{constant_time_eq, hmac_sha256};
+pub use fountain::FountainStream;
+use fountain::{FountainSolver, BLOCK_BITS, BLOCK_COUNT_BITS, MAX_BLOCKS, SEQUENCE_BITS};
+pub use record::{Record, RecordError, APPLICATION_RECORD_TYPES};
+pub use text::{decode_text, encode_text, encode_text_compact, TextEncoding, TextError};
+
+// Default limit for the length of received messages (see `MessageDecoder::set_max_message_len`)
+pub const DEFAULT_MAX_MESSAGE_LEN: usize = 4096;
+// Default maximum time between the reception of consecutive packets of a message
+// (the duration of a packet and a pause of the same duration, see `MessageDecoder::set_timeout`)
+pub const MESSAGE_TIMEOUT: f32 = 2. * BEEP_TIME * (PACKET_LEN as f32);
+
+// The first bit of each payload marks the start of a new message (1) or a continuation (0)
+pub(crate) const PAYLOAD_BITS: usize = PAYLOAD_LEN * SYMBOL_BITS;
+const USABLE_PAYLOAD_BITS: usize = PAYLOAD_BITS - 1;
+// Messages up to this length use the legacy format with a single length byte
+const LEGACY_MAX_MESSAGE_LEN: usize = 255;
+// The extended format starts with a zero byte followed by the format byte and the length as
+// varint. Old receivers read an empty message with invalid padding and drop it.
+// The format byte contains the version in the lower 4 bits and flags in the upper 4 bits.
+const EXTENDED_FORMAT_MARKER: u8 = 0;
+const FORMAT_VERSION: u8 = 1;
+const FORMAT_VERSION_MASK: u8 = 0x0f;
+// The message is followed by the CRC-32 of the header and the message (big-endian)
+const FORMAT_FLAG_CHECKSUM: u8 = 0x10;
+// The header is followed by a counter (big-endian) and the message by the truncated
+// HMAC-SHA256 of the header, the counter and the message
+const FORMAT_FLAG_AUTHENTICATION: u8 = 0x20;
+// The message is encrypted with ChaCha20, only together with the authentication.
+// The counter is preceded by the random ID of the sender (big-endian), see `cipher::encrypt`.
+const FORMAT_FLAG_ENCRYPTION: u8 = 0x40;
+// The header is followed by the source and the destination address
+const FORMAT_FLAG_ADDRESSES: u8 = 0x80;
+const FORMAT_FLAGS: u8 = FORMAT_FLAG_CHECKSUM
+    | FORMAT_FLAG_AUTHENTICATION
+    | FORMAT_FLAG_ENCRYPTION
+    | FORMAT_FLAG_ADDRESSES;
+const CHECKSUM_LEN: usize = 4;
+const COUNTER_LEN: usize = 4;
+const SENDER_ID_LEN: usize = 4;
+const TAG_LEN: usize = 8;
+const ADDRESSES_LEN: usize = 2;
+// Messages to this address are accepted by all receivers
+pub const BROADCAST_ADDRESS: u8 = 0xff;
+// Lengths are limited to 32 bits
+const MAX_VARINT_LEN: usize = 5;
+// Marker, format byte and length of the extended format
+const MAX_HEADER_LEN: usize = 2 + MAX_VARINT_LEN;
+// In fragmented mode each payload starts with the message ID, the index of the fragment and
+// the number of fragments minus one. The remaining bits contain the framed message.
+pub(crate) const MESSAGE_ID_BITS: usize = 4;
+const MESSAGE_IDS: usize = 1 << MESSAGE_ID_BITS;
+pub(crate) const FRAGMENT_INDEX_BITS: usize = 8;
+const FRAGMENT_DATA_BITS: usize = PAYLOAD_BITS - MESSAGE_ID_BITS - 2 * FRAGMENT_INDEX_BITS;
+pub const MAX_FRAGMENTS: usize = 1 << FRAGMENT_INDEX_BITS;
+// Limit for the length of messages in fragmented mode with header and checksum.
+// Authentication reduces the limit by 12 bytes, encryption by 4 more and addresses by 2 bytes.
+pub const MAX_FRAGMENTED_MESSAGE_LEN: usize =
+    MAX_FRAGMENTS * FRAGMENT_DATA_BITS / 8 - MAX_HEADER_LEN - CHECKSUM_LEN;
+// Limit for the length of fountain-coded messages with header and checksum.
+// Authentication reduces the limit by 12 bytes, encryption by 4 more and addresses by 2 bytes.
+pub const MAX_FOUNTAIN_MESSAGE_LEN: usize =
+    MAX_BLOCKS * BLOCK_BITS / 8 - MAX_HEADER_LEN - CHECKSUM_LEN;
+// Number of incomplete messages that are reassembled at once in fragmented and fountain mode
+const MAX_INCOMPLETE_MESSAGES: usize = 4;
+
+// Splits messages into payloads.
+// The message is prefixed with its length and zero-padded to fill the last payload.
+#[derive(Clone, Debug)]
+pub struct MessageEncoder {
+    checksum: bool,
+    fragmented: bool,
+    next_message_id: usize,
+    authentication_key: Option<Vec<u8>>,
+    // `None` until set by the application
+    counter: Option<u32>,
+    encryption: bool,
+    sender_id: Option<u32>,
+    address: Option<u8>,
+    destination: u8,
+}
+
+impl Default for MessageEncoder {
+    fn default() -> Self {
+        Self {
+            checksum: true,
+            fragmented: false,
+            next_message_id: 0,
+            authentication_key: None,
+            counter: None,
+            encryption: false,
+            sender_id: None,
+            address: None,
+            destination: BROADCAST_ADDRESS,
+        }
+    }
+}
+
+impl MessageEncoder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    // Append a CRC-32 to messages (default: `true`).
+    // Messages with checksum use the extended format, that older receivers silently drop.
+    // Messages up to 255 bytes without checksum can be received by older receivers.
+    pub fn set_checksum(&mut self, checksum: bool) {
+        self.checksum = checksum;
+    }
+
+    // Split messages into fragments with message ID, fragment index and fragment count
+    // (default: `false`). The receiver must be configured with `MessageDecoder::set_fragmented`.
+    pub fn set_fragmented(&mut self, fragmented: bool) {
+        self.fragmented = fragmented;
+    }
+
+    // Authenticate messages with the shared `key` (default: `None`).
+    // Each message contains a counter, that must increase for the receiver to accept it.
+    pub fn set_authentication(&mut self, key: Option<&[u8]>) {
+        self.authentication_key = key.map(<[u8]>::to_vec);
+    }
+
+    // Set the counter of the next authenticated message (default: `0`).
+    // The counter should be persisted, messages with old counters are rejected as replayed.
+    // Encryption requires the counter to be set or restored explicitly.
+    pub fn set_counter(&mut self, counter: u32) {
+        self.counter = Some(counter);
+    }
+
+    pub fn counter(&self) -> u32 {
+        self.counter.unwrap_or(0)
+    }
+
+    // Encrypt messages with the key from method `set_authentication` (default: `false`).
+    // Receivers without the key drop encrypted messages. Requires methods `set_counter` and
+    // `set_sender_id`, otherwise encoding panics.
+    pub fn set_encryption(&mut self, encryption: bool) {
+        self.encryption = encryption;
+    }
+
+    // Set the ID of this sender, that is added to encrypted messages. Choose a random ID for
+    // each session, it keeps the messages of senders with the same key apart.
+    pub fn set_sender_id(&mut self, sender_id: u32) {
+        self.sender_id = Some(sender_id);
+    }
+
+    // Add the source `address` and the destination address to messages (default: `None`)
+    pub fn set_address(&mut self, address: Option<u8>) {
+        self.address = address;
+    }
+
+    // Set the destination address of the following messages (default: `BROADCAST_ADDRESS`).
+    // Only used together with method `set_address`.
+    pub fn set_destination(&mut self, destination: u8) {
+        self.destination = destination;
+    }
+
+    pub fn encode(&mut self, message: &[u8]) -> Vec<[u8; PAYLOAD_LEN]> {
+        let bytes = self.frame(message);
+        if self.fragmented {
+            return self.fragment(&bytes);
+        }
+        let mut bits = Vec::with_capacity(bytes.len() * 9);
+        for (i, &byte) in bytes.iter().enumerate() {
+            for j in 0..8 {
+                // Insert the marker bit at the beginning of each payload
+                if (i * 8 + j) % USABLE_PAYLOAD_BITS == 0 {
+                    bits.push(i == 0);
+                }
+                bits.push((byte >> (7 - j)) & 1 == 1);
+            }
+        }
+        bits.chunks(PAYLOAD_BITS).map(bits_to_payload).collect()
+    }
+
+    // Get an endless stream of fountain-coded payloads for broadcasting the message.
+    // Receivers in fountain mode (see `MessageDecoder::set_fountain`) can start at any point
+    // and need about as many packets as the message has blocks plus one or two.
+    pub fn encode_fountain(&mut self, message: &[u8]) -> FountainStream {
+        let max_len = MAX_FOUNTAIN_MESSAGE_LEN - self.overhead_len();
+        assert!(
+            message.len() <= max_len,
+            "message too long: must be atmost {} bytes in fountain mode but is {}",
+            max_len,
+            message.len()
+        );
+        let id = self.next_message_id;
+        self.next_message_id = (id + 1) % MESSAGE_IDS;
+        FountainStream::new(id, &self.frame(message))
+    }
+
+    // Additional bytes for the authentication and the addresses
+    fn overhead_len(&self) -> usize {
+        let mut len = 0;
+        if self.authentication_key.is_some() {
+            len += COUNTER_LEN + TAG_LEN;
+        }
+        if self.encryption {
+            len += SENDER_ID_LEN;
+        }
+        if self.address.is_some() {
+            len += ADDRESSES_LEN;
+        }
+        len
+    }
+
+    // Prefix the message with the header and append the authentication tag and the checksum
+    fn frame(&mut self, message: &[u8]) -> Vec<u8> {
+        assert!(
+            message.len() <= u32::MAX as usize,
+            "message too long: must be atmost {} bytes but is {}",
+            u32::MAX,
+            message.len()
+        );
+        let mut bytes = Vec::with_capacity(MAX_HEADER_LEN + message.len() + CHECKSUM_LEN);
+        let key = self.authentication_key.as_ref();
+        assert!(
+            !self.encryption || key.is_some(),
+            "encryption requires an authentication key"
+        );
+        // Reusing the nonce would reveal the messages
+        assert!(
+            !self.encryption || self.counter.is_some(),
+            "encryption requires the counter to be set with method `set_counter`"
+        );
+        assert!(
+            !self.encryption || self.sender_id.is_some(),
+            "encryption requires a sender ID from method `set_sender_id`"
+        );
+        if message.len() <= LEGACY_MAX_MESSAGE_LEN
+            && !self.checksum
+            && key.is_none()
+            && self.address.is_none()
+        {
+            bytes.push(message.len() as u8);
+            bytes.extend(message);
+        } else {
+            bytes.push(EXTENDED_FORMAT_MARKER);
+            let mut format = FORMAT_VERSION;
+            if self.checksum {
+                format |= FORMAT_FLAG_CHECKSUM;
+            }
+            if key.is_some() {
+                format |= FORMAT_FLAG_AUTHENTICATION;
+            }
+            if self.encryption {
+                format |= FORMAT_FLAG_ENCRYPTION;
+            }
+            if self.address.is_some() {
+                format |= FORMAT_FLAG_ADDRESSES;
+            }
+            bytes.push(format);
+            write_varint(&mut bytes, message.len());
+            if let Some(address) = self.address {
+                bytes.push(address);
+                bytes.push(self.destination);
+            }
+            let counter = self.counter.unwrap_or(0);
+            let sender_id = self.sender_id.unwrap_or(0);
+            if self.encryption {
+                bytes.extend(sender_id.to_be_bytes().iter());
+            }
+            if key.is_some() {
+                bytes.extend(counter.to_be_bytes().iter());
+            }
+            let start = bytes.len();
+            bytes.extend(message);
+            if let Some(key) = key {
+                if self.encryption {
+                    cipher::encrypt(key, sender_id, counter, &mut bytes[start..]);
+                }
+                bytes.extend(hmac_sha256(key, &bytes)[..TAG_LEN].iter());
+                self.counter = Some(counter.checked_add(1).expect("counter overflow"));
+            }
+            if self.checksum {
+                let checksum = crc32(&bytes);
+                bytes.extend(checksum.to_be_bytes().iter());
+            }
+        }
+        bytes
+    }
+
+    fn fragment(&mut self, bytes: &[u8]) -> Vec<[u8; PAYLOAD_LEN]> {
+        let bits = bytes_to_bits(bytes);
+        let count = (bits.len() - 1) / FRAGMENT_DATA_BITS + 1;
+        assert!(
+            count <= MAX_FRAGMENTS,
+            "message too long: must be atmost {} bytes in fragmented mode",
+            MAX_FRAGMENTED_MESSAGE_LEN - self.overhead_len()
+        );
+        let id = self.next_message_id;
+        self.next_message_id = (id + 1) % MESSAGE_IDS;
+        bits.chunks(FRAGMENT_DATA_BITS)
+            .enumerate()
+            .map(|(index, data)| {
+                let mut fragment = Vec::with_capacity(PAYLOAD_BITS);
+                write_bits(&mut fragment, id, MESSAGE_ID_BITS);
+                write_bits(&mut fragment, index, FRAGMENT_INDEX_BITS);
+                write_bits(&mut fragment, count - 1, FRAGMENT_INDEX_BITS);
+                fragment.extend(data);
+                bits_to_payload(&fragment)
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MessageEvent {
+    Received(Vec<u8>),
+    // A complete message was dropped, because its checksum doesn't match
+    ChecksumMismatch,
+    // A complete message was dropped by the authentication
+    // (see method `MessageDecoder::set_authentication`)
+    Rejected(AuthError),
+    // A message with addresses (see method `MessageEncoder::set_address`)
+    ReceivedAddressed(Addresses, Vec<u8>),
+    // The following events are only emitted with `MessageDecoder::set_progress_events`.
+    // The first packet of a message was received.
+    Started,
+    // Received and total number of bytes of an incomplete message. In fragmented and fountain
+    // mode, the bytes include the header and the padding.
+    Progress(usize, usize),
+    // An incomplete message was dropped, because no packet was received within the timeout
+    TimedOut,
+    // A complete message was dropped, because its padding isn't zero
+    InvalidPadding,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Addresses {
+    pub source: u8,
+    pub destination: u8,
+}
+
+enum Parsed {
+    // Length of the complete message, when the header was received
+    Incomplete(Option<usize>),
+    Invalid,
+    InvalidPadding,
+    ChecksumMismatch,
+    // Range of the message in the received bytes, the format flags and the addresses
+    Complete(usize, usize, u8, Option<Addresses>),
+}
+
+// Missing fragments of a message in fragmented mode (see `MessageDecoder::incomplete_messages`)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IncompleteMessage {
+    pub id: u8,
+    pub count: usize,
+    // Indices of the missing fragments
+    pub missing: Vec<usize>,
+}
+
+#[derive(Clone, Debug)]
+struct PartialMessage {
+    id: usize,
+    // Data bits of the received fragments
+    fragments: Vec<Option<Vec<bool>>>,
+    last_fragment_at: f64,
+}
+
+// A reassembled message in fragmented or fountain mode.
+// Packets of the message are ignored until the timeout expires, other packets with the same ID
+// and count start a new message.
+#[derive(Clone, Debug)]
+struct CompletedMessage {
+    id: usize,
+    count: usize,
+    // Data bits of the fragments or the decoded blocks
+    bits: Vec<bool>,
+    // Fragments that were received again after the completion
+    repeated: Vec<bool>,
+    last_packet_at: f64,
+}
+
+// Reassembles messages from received payloads
+#[derive(Clone, Debug)]
+pub struct MessageDecoder {
+    max_message_len: usize,
+    require_checksum: bool,
+    active_message: Vec<u8>,
+    // Bits of the incomplete last byte
+    remaining_bits: Vec<bool>,
+    last_payload_at: f64,
+    fragmented: bool,
+    partial_messages: Vec<PartialMessage>,
+    completed_messages: Vec<CompletedMessage>,
+    fountain: bool,
+    // ID, decoder and time of the last packet of incomplete fountain-coded messages,
+    // the message that was updated least recently first
+    fountain_messages: Vec<(usize, FountainSolver, f64)>,
+    authentication_key: Option<Vec<u8>>,
+    // Counter of the last accepted message of each sender ID. Authenticated messages without
+    // encryption have no sender ID.
+    last_counters: BTreeMap<Option<u32>, u32>,
+    address: Option<u8>,
+    timeout: f64,
+    progress_events: bool,
+    events: VecDeque<MessageEvent>,
+}
+
+impl Default for MessageDecoder {
+    fn default() -> Self {
+        Self {
+            max_message_len: DEFAULT_MAX_MESSAGE_LEN,
+            require_checksum: false,
+            active_message: Vec::new(),
+            remaining_bits: Vec::new(),
+            last_payload_at: 0.,
+            fragmented: false,
+            partial_messages: Vec::new(),
+            completed_messages: Vec::new(),
+            fountain: false,
+            fountain_messages: Vec::new(),
+            authentication_key: None,
+            last_counters: BTreeMap::new(),
+            address: None,
+            timeout: MESSAGE_TIMEOUT as f64,
+            progress_events: false,
+            events: VecDeque::new(),
+        }
+    }
+}
+
+impl MessageDecoder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    // Drop messages that are longer than `len` bytes (default: `DEFAULT_MAX_MESSAGE_LEN`)
+    pub fn set_max_message_len(&mut self, len: usize) {
+        self.max_message_len = len;
+    }
+
+    // Drop messages without checksum (default: `false`)
+    pub fn set_require_checksum(&mut self, require_checksum: bool) {
+        self.require_checksum = require_checksum;
+    }
+
+    // Receive fragments from a `MessageEncoder` in fragmented mode (default: `false`)
+    pub fn set_fragmented(&mut self, fragmented: bool) {
+        self.fragmented = fragmented;
+    }
+
+    // Receive fountain-coded payloads from `MessageEncoder::encode_fountain` (default: `false`).
+    // Takes precedence over the fragmented mode.
+    pub fn set_fountain(&mut self, fountain: bool) {
+        self.fountain = fountain;
+    }
+
+    // Only accept messages, that are authenticated with the shared `key` and have a greater
+    // counter than the last accepted message from the same sender ID (default: `None`).
+    // Without key, the authentication of messages is ignored.
+    pub fn set_authentication(&mut self, key: Option<&[u8]>) {
+        self.authentication_key = key.map(<[u8]>::to_vec);
+    }
+
+    // Set the counter of the last accepted message from `sender_id` (default: `None`).
+    // Messages without encryption have the sender ID `None`.
+    pub fn set_last_counter(&mut self, sender_id: Option<u32>, counter: Option<u32>) {
+        match counter {
+            Some(counter) => self.last_counters.insert(sender_id, counter),
+            None => self.last_counters.remove(&sender_id),
+        };
+    }
+
+    pub fn last_counter(&self, sender_id: Option<u32>) -> Option<u32> {
+        self.last_counters.get(&sender_id).copied()
+    }
+
+    // The counters of the last accepted messages of all senders, e.g. for persisting them
+    pub fn last_counters(&self) -> &BTreeMap<Option<u32>, u32> {
+        &self.last_counters
+    }
+
+    // Drop messages with addresses, unless they are sent to `address` or to `BROADCAST_ADDRESS`
+    // (default: `None`). Messages without addresses are always accepted.
+    pub fn set_address(&mut self, address: Option<u8>) {
+        self.address = address;
+    }
+
+    // Drop incomplete messages, when no packet was received for `timeout` seconds
+    // (default: `MESSAGE_TIMEOUT`). Repeated packets of completed messages in fragmented and
+    // fountain mode are ignored for the same time.
+    pub fn set_timeout(&mut self, timeout: f64) {
+        self.timeout = timeout;
+    }
+
+    // Emit events about the progress of incomplete messages, timeouts and invalid padding
+    // (default: `false`)
+    pub fn set_progress_events(&mut self, progress_events: bool) {
+        self.progress_events = progress_events;
+    }
+
+    // Get the missing fragments of messages that are reassembled in fragmented mode
+    pub fn incomplete_messages(&self) -> Vec<IncompleteMessage> {
+        self.partial_messages
+            .iter()
+            .map(|partial| IncompleteMessage {
+                id: partial.id as u8,
+                count: partial.fragments.len(),
+                missing: (partial.fragments.iter().enumerate())
+                    .filter(|(_, fragment)| fragment.is_none())
+                    .map(|(index, _)| index)
+                    .collect(),
+            })
+            .collect()
+    }
+
+    fn reset(&mut self) {
+        self.active_message.clear();
+        self.remaining_bits.clear();
+    }
+
+    // Get the next received message or error
+    pub fn poll_event(&mut self) -> Option<MessageEvent> {
+        self.events.pop_front()
+    }
+
+    fn push_progress_event(&mut self, event: MessageEvent) {
+        if self.progress_events {
+            self.events.push_back(event);
+        }
+    }
+
+    // Drop incomplete messages, when the timeout expired at `timestamp`.
+    // Called by method `push_payload`, call it regularly to get `MessageEvent::TimedOut`
+    // without receiving more payloads.
+    pub fn check_timeout(&mut self, timestamp: f64) {
+        let timeout = self.timeout;
+        let mut timed_out = 0;
+        if !(self.active_message.is_empty() && self.remaining_bits.is_empty())
+            && timestamp - self.last_payload_at > timeout
+        {
+            self.reset();
+            timed_out += 1;
+        }
+        let partial_messages_len = self.partial_messages.len();
+        self.partial_messages
+            .retain(|partial| timestamp - partial.last_fragment_at <= timeout);
+        timed_out += partial_messages_len - self.partial_messages.len();
+        let fountain_messages_len = self.fountain_messages.len();
+        self.fountain_messages
+            .retain(|&(_, _, last_packet_at)| timestamp - last_packet_at <= timeout);
+        timed_out += fountain_messages_len - self.fountain_messages.len();
+        for _ in 0..timed_out {
+            self.push_progress_event(MessageEvent::TimedOut);
+        }
+    }
+
+    // Commit a received payload, `timestamp` is the time of the reception in seconds.
+    // The message is available from method `poll_event`, when its last payload was received.
+    pub fn push_payload(&mut self, payload: &[u8; PAYLOAD_LEN], timestamp: f64) {
+        let bits = payload_to_bits(payload);
+        self.check_timeout(timestamp);
+        if self.fountain {
+            self.push_fountain(&bits, timestamp);
+            return;
+        }
+        if self.fragmented {
+            self.push_fragment(&bits, timestamp);
+            return;
+        }
+        // Check for marker of new message
+        if bits[0] {
+            self.reset();
+            self.push_progress_event(MessageEvent::Started);
+        } else if self.active_message.is_empty() && self.remaining_bits.is_empty() {
+            return;
+        }
+        self.last_payload_at = timestamp;
+        self.remaining_bits.extend(bits.iter().skip(1));
+        let bytes_len = self.remaining_bits.len() / 8 * 8;
+        self.active_message
+            .extend(self.remaining_bits[..bytes_len].chunks(8).map(bits_to_byte));
+        self.remaining_bits.drain(..bytes_len);
+        let event = match self.parse(&self.active_message) {
+            Parsed::Incomplete(total) => {
+                if let Some(total) = total {
+                    let received = self.active_message.len();
+                    self.push_progress_event(MessageEvent::Progress(received, total));
+                }
+                return;
+            }
+            Parsed::Invalid => None,
+            Parsed::InvalidPadding => {
+                Some(MessageEvent::InvalidPadding).filter(|_| self.progress_events)
+            }
+            Parsed::ChecksumMismatch => Some(MessageEvent::ChecksumMismatch),
+            Parsed::Complete(start, end, format, addresses) => {
+                let bytes = std::mem::take(&mut self.active_message);
+                Some(self.authenticate(&bytes, start, end, format, addresses))
+            }
+        };
+        self.reset();
+        self.events.extend(event);
+    }
+
+    fn push_fragment(&mut self, bits: &[bool], timestamp: f64) {
+        let (id, index, count) = fragment_header(bits);
+        if index >= count {
+            return;
+        }
+        let data = &bits[PAYLOAD_BITS - FRAGMENT_DATA_BITS..];
+        let mut fragments = vec![None; count];
+        if let Some(position) = self.find_completed(id, count, timestamp) {
+            let completed = &mut self.completed_messages[position];
+            let range = index * FRAGMENT_DATA_BITS..(index + 1) * FRAGMENT_DATA_BITS;
+            if completed.bits[range] == *data {
+                completed.repeated[index] = true;
+                completed.last_packet_at = timestamp;
+                return;
+            }
+            // A new message with the same ID and count. The fragments, that it has in common
+            // with the completed message, were taken for repeats.
+            let completed = self.completed_messages.remove(position);
+            let repeated = completed
+                .bits
+                .chunks(FRAGMENT_DATA_BITS)
+                .zip(completed.repeated);
+            for (fragment, (bits, repeated)) in fragments.iter_mut().zip(repeated) {
+                if repeated {
+                    *fragment = Some(bits.to_vec());
+                }
+            }
+        }
+        // Another message with the same ID replaces the old one
+        self.partial_messages
+            .retain(|partial| partial.id != id || partial.fragments.len() == count);
+        let position = match self.partial_messages.iter().position(|p| p.id == id) {
+            Some(position) => position,
+            None => {
+                if self.partial_messages.len() >= MAX_INCOMPLETE_MESSAGES {
+                    // Drop the message that was updated least recently
+                    self.partial_messages.remove(0);
+                }
+                self.partial_messages.push(PartialMessage {
+                    id,
+                    fragments,
+                    last_fragment_at: timestamp,
+                });
+                self.push_progress_event(MessageEvent::Started);
+                self.partial_messages.len() - 1
+            }
+        };
+        let mut partial = self.partial_messages.remove(position);
+        partial.fragments[index] = Some(data.to_vec());
+        partial.last_fragment_at = timestamp;
+        if partial.fragments.iter().any(Option::is_none) {
+            let received = partial.fragments.iter().flatten().count();
+            self.partial_messages.push(partial);
+            self.push_progress_event(MessageEvent::Progress(
+                received * FRAGMENT_DATA_BITS / 8,
+                count * FRAGMENT_DATA_BITS / 8,
+            ));
+            return;
+        }
+        let bits: Vec<bool> = partial.fragments.into_iter().flatten().flatten().collect();
+        self.push_reassembled(&bits);
+        self.completed_messages.push(CompletedMessage {
+            id,
+            count,
+            bits,
+            repeated: vec![false; count],
+            last_packet_at: timestamp,
+        });
+    }
+
+    fn push_fountain(&mut self, bits: &[bool], timestamp: f64) {
+        let id = read_bits(bits, 0, MESSAGE_ID_BITS);
+        let count = read_bits(bits, MESSAGE_ID_BITS, BLOCK_COUNT_BITS) + 1;
+        let sequence = read_bits(bits, MESSAGE_ID_BITS + BLOCK_COUNT_BITS, SEQUENCE_BITS);
+        let block = read_bits(bits, PAYLOAD_BITS - BLOCK_BITS, BLOCK_BITS) as u32;
+        let row = fountain::combination(id, count, sequence);
+        if let Some(position) = self.find_completed(id, count, timestamp) {
+            let completed = &mut self.completed_messages[position];
+            let expected = (0..count)
+                .filter(|&i| row & (1 << i) != 0)
+                .map(|i| read_bits(&completed.bits, i * BLOCK_BITS, BLOCK_BITS) as u32)
+                .fold(0, |expected, block| expected ^ block);
+            if block == expected {
+                completed.last_packet_at = timestamp;
+                return;
+            }
+            // A new message with the same ID and block count
+            self.completed_messages.remove(position);
+        }
+        let position = match (self.fountain_messages.iter())
+            .position(|(active_id, solver, _)| *active_id == id && solver.block_count() == count)
+        {
+            Some(position) => position,
+            None => {
+                if self.fountain_messages.len() >= MAX_INCOMPLETE_MESSAGES {
+                    // Drop the message that was updated least recently
+                    self.fountain_messages.remove(0);
+                }
+                self.fountain_messages
+                    .push((id, FountainSolver::new(count), timestamp));
+                self.push_progress_event(MessageEvent::Started);
+                self.fountain_messages.len() - 1
+            }
+        };
+        let (_, mut solver, _) = self.fountain_messages.remove(position);
+        solver.push(row, block);
+        let blocks = match solver.solve() {
+            Some(blocks) => blocks,
+            None => {
+                let received = solver.rank();
+                self.fountain_messages.push((id, solver, timestamp));
+                self.push_progress_event(MessageEvent::Progress(
+                    received * BLOCK_BITS / 8,
+                    count * BLOCK_BITS / 8,
+                ));
+                return;
+            }
+        };
+        let mut bits = Vec::with_capacity(count * BLOCK_BITS);
+        for &block in blocks.iter() {
+            write_bits(&mut bits, block as usize, BLOCK_BITS);
+        }
+        self.push_reassembled(&bits);
+        self.completed_messages.push(CompletedMessage {
+            id,
+            count,
+            bits,
+            repeated: vec![false; count],
+            last_packet_at: timestamp,
+        });
+    }
+
+    // Find a received message with the ID and count, whose packets are still ignored
+    fn find_completed(&mut self, id: usize, count: usize, timestamp: f64) -> Option<usize> {
+        let timeout = self.timeout;
+        self.completed_messages
+            .retain(|completed| timestamp - completed.last_packet_at <= timeout);
+        (self.completed_messages.iter())
+            .position(|completed| completed.id == id && completed.count == count)
+    }
+
+    // Parse the bits of a message that was reassembled from fragments or fountain-coded packets
+    fn push_reassembled(&mut self, bits: &[bool]) {
+        let bytes: Vec<u8> = bits.chunks_exact(8).map(bits_to_byte).collect();
+        let event = match self.parse(&bytes) {
+            Parsed::Incomplete(_) | Parsed::Invalid => None,
+            Parsed::InvalidPadding => {
+                Some(MessageEvent::InvalidPadding).filter(|_| self.progress_events)
+            }
+            Parsed::ChecksumMismatch => Some(MessageEvent::ChecksumMismatch),
+            Parsed::Complete(start, end, format, addresses) => {
+                Some(self.authenticate(&bytes, start, end, format, addresses))
+            }
+        };
+        self.events.extend(event);
+    }
+
+    // Check the authentication tag and the counter of a complete message and decrypt it
+    fn authenticate(
+        &mut self,
+        bytes: &[u8],
+        start: usize,
+        end: usize,
+        format: u8,
+        addresses: Option<Addresses>,
+    ) -> MessageEvent {
+        let received = |message| match addresses {
+            Some(addresses) => MessageEvent::ReceivedAddressed(addresses, message),
+            None => MessageEvent::Received(message),
+        };
+        let key = match self.authentication_key.as_ref() {
+            Some(key) => key,
+            None => return received(bytes[start..end].to_vec()),
+        };
+        if format & FORMAT_FLAG_AUTHENTICATION == 0 {
+            return MessageEvent::Rejected(AuthError::Unauthenticated);
+        }
+        if !constant_time_eq(
+            &bytes[end..end + TAG_LEN],
+            &hmac_sha256(key, &bytes[..end])[..TAG_LEN],
+        ) {
+            return MessageEvent::Rejected(AuthError::InvalidTag);
+        }
+        let mut counter = [0; COUNTER_LEN];
+        counter.copy_from_slice(&bytes[start - COUNTER_LEN..start]);
+        let counter = u32::from_be_bytes(counter);
+        let sender_id = if format & FORMAT_FLAG_ENCRYPTION != 0 {
+            let mut sender_id = [0; SENDER_ID_LEN];
+            let sender_id_start = start - COUNTER_LEN - SENDER_ID_LEN;
+            sender_id.copy_from_slice(&bytes[sender_id_start..sender_id_start + SENDER_ID_LEN]);
+            Some(u32::from_be_bytes(sender_id))
+        } else {
+            None
+        };
+        if self
+            .last_counter(sender_id)
+            .map_or(false, |last_counter| counter <= last_counter)
+        {
+            return MessageEvent::Rejected(AuthError::Replayed);
+        }
+        self.last_counters.insert(sender_id, counter);
+        let mut message = bytes[start..end].to_vec();
+        if let Some(sender_id) = sender_id {
+            cipher::encrypt(key, sender_id, counter, &mut message);
+        }
+        received(message)
+    }
+
+    fn parse(&self, bytes: &[u8]) -> Parsed {
+        let (header_len, message_len, format) = match (bytes.first(), bytes.get(1)) {
+            (None, _) => return Parsed::Incomplete(None),
+            (Some(&len), _) if len != EXTENDED_FORMAT_MARKER => (1, len as usize, 0),
+            (Some(_), None) => return Parsed::Incomplete(None),
+            // Empty message in the legacy format
+            (Some(_), Some(0)) => (1, 0, 0),
+            (Some(_), Some(&format))
+                if format & FORMAT_VERSION_MASK == FORMAT_VERSION
+                    && format & !FORMAT_VERSION_MASK & !FORMAT_FLAGS == 0 =>
+            {
+                match read_varint(&bytes[2..]) {
+                    Ok(Some((len, varint_len))) => (2 + varint_len, len, format),
+                    Ok(None) => return Parsed::Incomplete(None),
+                    Err(()) => return Parsed::Invalid,
+                }
+            }
+            // Unknown version or flags
+            (Some(_), Some(_)) => return Parsed::Invalid,
+        };
+        let checksum = format & FORMAT_FLAG_CHECKSUM != 0;
+        let authenticated = format & FORMAT_FLAG_AUTHENTICATION != 0;
+        if message_len > self.max_message_len || (self.require_checksum && !checksum) {
+            return Parsed::Invalid;
+        }
+        // Encrypted messages can't be read without key
+        let encrypted = format & FORMAT_FLAG_ENCRYPTION != 0;
+        if encrypted && !(authenticated && self.authentication_key.is_some()) {
+            return Parsed::Invalid;
+        }
+        let addressed = format & FORMAT_FLAG_ADDRESSES != 0;
+        let addresses_start = header_len;
+        let header_len = header_len + if addressed { ADDRESSES_LEN } else { 0 };
+        let header_len = header_len + if encrypted { SENDER_ID_LEN } else { 0 };
+        let header_len = header_len + if authenticated { COUNTER_LEN } else { 0 };
+        let message_end = header_len + message_len;
+        let tag_end = message_end + if authenticated { TAG_LEN } else { 0 };
+        let end = tag_end + if checksum { CHECKSUM_LEN } else { 0 };
+        if bytes.len() < end {
+            return Parsed::Incomplete(Some(end));
+        }
+        // Check zero padding
+        if bytes[end..].iter().any(|&byte| byte != 0) {
+            return Parsed::InvalidPadding;
+        }
+        if checksum && bytes[tag_end..end] != crc32(&bytes[..tag_end]).to_be_bytes() {
+            return Parsed::ChecksumMismatch;
+        }
+        let addresses = Some(Addresses {
+            source: bytes[addresses_start],
+            destination: bytes[addresses_start + 1],
+        })
+        .filter(|_| addressed);
+        if addresses.map_or(false, |addresses| {
+            self.address.map_or(false, |address| {
+                addresses.destination != address && addresses.destination != BROADCAST_ADDRESS
+            })
+        }) {
+            return Parsed::Invalid;
+        }
+        Parsed::Complete(header_len, message_end, format, addresses)
+    }
+}
+
+// Unsigned LEB128
+fn write_varint(bytes: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+// Returns the value and the number of used bytes, `None` if the varint is incomplete
+fn read_varint(bytes: &[u8]) -> Result<Option<(usize, usize)>, ()> {
+    let mut value = 0u64;
+    for (i, &byte) in bytes.iter().enumerate() {
+        if i >= MAX_VARINT_LEN {
+            return Err(());
+        }
+        value |= ((byte & 0x7f) as u64) << (7 * i);
+        if byte & 0x80 == 0 {
+            if value > u32::MAX as u64 {
+                return Err(());
+            }
+            return Ok(Some((value as usize, i + 1)));
+        }
+    }
+    Ok(None)
+}
+
+// CRC-32 (IEEE 802.3)
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &byte in bytes.iter() {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+// Get the message ID, the fragment index and the fragment count of a payload in fragmented mode
+pub(crate) fn fragment_header(bits: &[bool]) -> (usize, usize, usize) {
+    let id = read_bits(bits, 0, MESSAGE_ID_BITS);
+    let index = read_bits(bits, MESSAGE_ID_BITS, FRAGMENT_INDEX_BITS);
+    let count = read_bits(
+        bits,
+        MESSAGE_ID_BITS + FRAGMENT_INDEX_BITS,
+        FRAGMENT_INDEX_BITS,
+    ) + 1;
+    (id, index, count)
+}
+
+pub(crate) fn write_bits(bits: &mut Vec<bool>, value: usize, len: usize) {
+    bits.extend((0..len).rev().map(|i| (value >> i) & 1 == 1));
+}
+
+pub(crate) fn read_bits(bits: &[bool], start: usize, len: usize) -> usize {
+    (bits[start..start + len].iter()).fold(0, |value, &bit| (value << 1) | (bit as usize))
+}
+
+fn bytes_to_bits(bytes: &[u8]) -> Vec<bool> {
+    let mut bits = Vec::with_capacity(bytes.len() * 8);
+    for &byte in bytes.iter() {
+        write_bits(&mut bits, byte as usize, 8);
+    }
+    bits
+}
+
+pub(crate) fn payload_to_bits(payload: &[u8; PAYLOAD_LEN]) -> Vec<bool> {
+    let mut bits = Vec::with_capacity(PAYLOAD_BITS);
+    for &symbol in payload.iter() {
+        for i in (0..SYMBOL_BITS).rev() {
+            bits.push((symbol >> i) & 1 == 1);
+        }
+    }
+    bits
+}
+
+// Missing bits are filled with zeros
+pub(crate) fn bits_to_payload(bits: &[bool]) -> [u8; PAYLOAD_LEN] {
+    let mut payload = [0u8; PAYLOAD_LEN];
+    for (i, symbol) in payload.iter_mut().enumerate() {
+        for j in 0..SYMBOL_BITS {
+            if let Some(true) = bits.get(i * SYMBOL_BITS + j) {
+                *symbol += 1 << (SYMBOL_BITS - j - 1);
+            }
+        }
+        debug_assert!((*symbol as usize) < SYMBOL_COUNT);
+    }
+    payload
+}
+
+fn bits_to_byte(bits: &[bool]) -> u8 {
+    bits.iter().fold(0, |byte, &bit| (byte << 1) | (bit as u8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSAGES: [&str; 5] = ["Test Message", "Another Message", "", "Hi", "😀😁😂😃😄😅"];
+
+    fn receive_all(
+        decoder: &mut MessageDecoder,
+        payloads: &[[u8; PAYLOAD_LEN]],
+    ) -> Vec<MessageEvent> {
+        let mut events = Vec::new();
+        for payload in payloads.iter() {
+            decoder.push_payload(payload, 0.);
+            events.extend(std::iter::from_fn(|| decoder.poll_event()));
+        }
+        events
+    }
+
+    fn received(message: &[u8]) -> Vec<MessageEvent> {
+        vec![MessageEvent::Received(message.to_vec())]
+    }
+
+    fn payloads_len(header_len: usize, message_len: usize) -> usize {
+        ((header_len + message_len) * 8 - 1) / USABLE_PAYLOAD_BITS + 1
+    }
+
+    // Test encoding and decoding of messages
+    #[test]
+    fn test_encode_and_decode() {
+        let mut encoder = MessageEncoder::new();
+        let mut decoder = MessageDecoder::new();
+        let mut messages: Vec<Vec<u8>> = MESSAGES.iter().map(|m| m.as_bytes().to_vec()).collect();
+        messages.push(vec![0xa5; 255]);
+        messages.push(vec![0x5a; 256]);
+        messages.push((0..DEFAULT_MAX_MESSAGE_LEN).map(|i| i as u8).collect());
+        for &checksum in [false, true].iter() {
+            encoder.set_checksum(checksum);
+            for message in messages.iter() {
+                let payloads = encoder.encode(message);
+                let expected_len = match (message.len(), checksum) {
+                    (len, false) if len <= LEGACY_MAX_MESSAGE_LEN => payloads_len(1, len),
+                    (len, false) => payloads_len(4, len),
+                    (len, true) if len < 128 => payloads_len(3 + CHECKSUM_LEN, len),
+                    (len, true) => payloads_len(4 + CHECKSUM_LEN, len),
+                };
+                assert_eq!(payloads.len(), expected_len);
+                assert_eq!(receive_all(&mut decoder, &payloads), received(message));
+            }
+        }
+    }
+
+    // Test that incomplete and invalid messages are dropped
+    #[test]
+    fn test_invalid_messages() {
+        let mut encoder = MessageEncoder::new();
+        encoder.set_checksum(false);
+        let mut decoder = MessageDecoder::new();
+        let payloads = encoder.encode(MESSAGES[1].as_bytes());
+        assert_eq!(payloads.len(), 3);
+        // Missing start
+        assert!(receive_all(&mut decoder, &payloads[1..]).is_empty());
+        // Interrupted by a new message
+        let mut interrupted = payloads[..2].to_vec();
+        interrupted.extend(encoder.encode(MESSAGES[0].as_bytes()));
+        assert_eq!(
+            receive_all(&mut decoder, &interrupted),
+            received(MESSAGES[0].as_bytes())
+        );
+        // Timeout
+        let timeout = MESSAGE_TIMEOUT as f64;
+        decoder.push_payload(&payloads[0], 0.);
+        decoder.push_payload(&payloads[1], timeout);
+        decoder.push_payload(&payloads[2], timeout * 2.5);
+        assert_eq!(decoder.poll_event(), None);
+        // Invalid padding
+        let mut payloads = encoder.encode(MESSAGES[3].as_bytes());
+        payloads[0][PAYLOAD_LEN - 2] = 1;
+        assert!(receive_all(&mut decoder, &payloads).is_empty());
+        // Missing checksum
+        decoder.set_require_checksum(true);
+        assert!(receive_all(&mut decoder, &encoder.encode(MESSAGES[3].as_bytes())).is_empty());
+    }
+
+    // Test the limit for the length and unknown versions of the extended format
+    #[test]
+    fn test_extended_format() {
+        let mut encoder = MessageEncoder::new();
+        encoder.set_checksum(false);
+        let mut decoder = MessageDecoder::new();
+        decoder.set_max_message_len(300);
+        let long_message = vec![1; 301];
+        assert!(receive_all(&mut decoder, &encoder.encode(&long_message)).is_empty());
+        let message = vec![1; 300];
+        assert_eq!(
+            receive_all(&mut decoder, &encoder.encode(&message)),
+            received(&message)
+        );
+        // Change the version in the second byte
+        let mut payloads = encoder.encode(&message);
+        payloads[0][2] ^= 1;
+        assert!(receive_all(&mut decoder, &payloads).is_empty());
+    }
+
+    // Test that the checksum detects packets of another message
+    #[test]
+    fn test_checksum() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        let mut encoder = MessageEncoder::new();
+        let mut decoder = MessageDecoder::new();
+        let mut payloads = encoder.encode(MESSAGES[0].as_bytes());
+        let other_payloads = encoder.encode(MESSAGES[1].as_bytes());
+        payloads[1] = other_payloads[1];
+        assert_eq!(
+            receive_all(&mut decoder, &payloads),
+            vec![MessageEvent::ChecksumMismatch]
+        );
+    }
+
+    // Test reassembly of fragments in any order with repeats
+    #[test]
+    fn test_fragments() {
+        let mut encoder = MessageEncoder::new();
+        encoder.set_fragmented(true);
+        let mut decoder = MessageDecoder::new();
+        decoder.set_fragmented(true);
+        let mut messages: Vec<Vec<u8>> = MESSAGES.iter().map(|m| m.as_bytes().to_vec()).collect();
+        messages.push((0..MAX_FRAGMENTED_MESSAGE_LEN).map(|i| i as u8).collect());
+        for message in messages.iter() {
+            let payloads = encoder.encode(message);
+            let mut shuffled: Vec<_> = payloads.iter().rev().cloned().collect();
+            shuffled.extend(payloads.iter().step_by(2));
+            assert_eq!(receive_all(&mut decoder, &shuffled), received(message));
+        }
+        assert_eq!(encoder.encode(&messages[5]).len(), MAX_FRAGMENTS);
+        // Repeats of a received message are ignored until the timeout expires
+        let payloads = encoder.encode(MESSAGES[0].as_bytes());
+        let timeout = MESSAGE_TIMEOUT as f64;
+        for &timestamp in [0., timeout, timeout * 2.].iter() {
+            for payload in payloads.iter() {
+                decoder.push_payload(payload, timestamp);
+            }
+        }
+        assert_eq!(decoder.poll_event(), received(MESSAGES[0].as_bytes()).pop());
+        assert_eq!(decoder.poll_event(), None);
+        for payload in payloads.iter() {
+            decoder.push_payload(payload, timeout * 3.5);
+        }
+        assert_eq!(decoder.poll_event(), received(MESSAGES[0].as_bytes()).pop());
+    }
+
+    // Test that a new message with the ID and count of a received message isn't taken for a
+    // repeat, even if its first fragments are the same
+    #[test]
+    fn test_same_message_id() {
+        let messages: [&[u8]; 2] = [b"Hello World, this is A", b"Hello World, this is B"];
+        let mut decoder = MessageDecoder::new();
+        decoder.set_fragmented(true);
+        for message in messages.iter() {
+            let mut encoder = MessageEncoder::new();
+            encoder.set_fragmented(true);
+            let payloads = encoder.encode(message);
+            assert_eq!(receive_all(&mut decoder, &payloads), received(message));
+        }
+        let mut decoder = MessageDecoder::new();
+        decoder.set_fountain(true);
+        for message in messages.iter() {
+            let stream = MessageEncoder::new().encode_fountain(message);
+            let payloads: Vec<_> = stream.take(20).collect();
+            assert_eq!(receive_all(&mut decoder, &payloads), received(message));
+        }
+    }
+
+    // Test that messages at the length limit fit with the longest header
+    #[test]
+    fn test_length_limits() {
+        let mut header = vec![EXTENDED_FORMAT_MARKER, FORMAT_VERSION];
+        write_varint(&mut header, u32::MAX as usize);
+        assert_eq!(header.len(), MAX_HEADER_LEN);
+        let mut encoder = MessageEncoder::new();
+        encoder.set_checksum(true);
+        encoder.set_authentication(Some(b"secret"));
+        encoder.set_encryption(true);
+        encoder.set_counter(0);
+        encoder.set_sender_id(1);
+        encoder.set_address(Some(1));
+        let overhead_len = COUNTER_LEN + TAG_LEN + SENDER_ID_LEN + ADDRESSES_LEN;
+        let addresses = Addresses {
+            source: 1,
+            destination: BROADCAST_ADDRESS,
+        };
+        let mut decoder = MessageDecoder::new();
+        decoder.set_authentication(Some(b"secret"));
+        decoder.set_fragmented(true);
+        let message = vec![0x5a; MAX_FRAGMENTED_MESSAGE_LEN - overhead_len];
+        encoder.set_fragmented(true);
+        let payloads = encoder.encode(&message);
+        assert!(payloads.len() <= MAX_FRAGMENTS);
+        assert_eq!(
+            receive_all(&mut decoder, &payloads),
+            vec![MessageEvent::ReceivedAddressed(addresses, message)]
+        );
+        let mut decoder = MessageDecoder::new();
+        decoder.set_authentication(Some(b"secret"));
+        decoder.set_fountain(true);
+        let message = vec![0x5a; MAX_FOUNTAIN_MESSAGE_LEN - overhead_len];
+        let payloads: Vec<_> = encoder
+            .encode_fountain(&message)
+            .take(MAX_BLOCKS + 10)
+            .collect();
+        assert_eq!(
+            receive_all(&mut decoder, &payloads),
+            vec![MessageEvent::ReceivedAddressed(addresses, message)]
+        );
+    }
+
+    // Test the detection of missing fragments and interleaved messages
+    #[test]
+    fn test_missing_fragments() {
+        let mut encoder = MessageEncoder::new();
+        encoder.set_fragmented(true);
+        let mut decoder = MessageDecoder::new();
+        decoder.set_fragmented(true);
+        let message = vec![0x5a; 20];
+        let other_message = vec![0xa5; 30];
+        let payloads = encoder.encode(&message);
+        let other_payloads = encoder.encode(&other_message);
+        assert_eq!((payloads.len(), other_payloads.len()), (8, 10));
+        let mut interleaved = Vec::new();
+        for (i, other_payload) in other_payloads.iter().enumerate() {
+            if i != 9 {
+                interleaved.push(*other_payload);
+            }
+            if let Some(payload) = payloads.get(i).filter(|_| i != 1 && i != 3) {
+                interleaved.push(*payload);
+            }
+        }
+        assert!(receive_all(&mut decoder, &interleaved).is_empty());
+        let incomplete = |id, count, missing: &[usize]| IncompleteMessage {
+            id,
+            count,
+            missing: missing.to_vec(),
+        };
+        assert_eq!(
+            decoder.incomplete_messages(),
+            vec![incomplete(0, 8, &[1, 3]), incomplete(1, 10, &[9])]
+        );
+        assert_eq!(
+            receive_all(&mut decoder, &[payloads[3], other_payloads[9], payloads[1]]),
+            vec![
+                MessageEvent::Received(other_message),
+                MessageEvent::Received(message.clone())
+            ]
+        );
+        assert!(decoder.incomplete_messages().is_empty());
+        // The oldest incomplete message is dropped
+        let mut first_payloads = Vec::new();
+        for _ in 0..=MAX_INCOMPLETE_MESSAGES {
+            first_payloads.push(encoder.encode(&message)[0]);
+        }
+        assert!(receive_all(&mut decoder, &first_payloads).is_empty());
+        let ids: Vec<u8> = (decoder.incomplete_messages().iter())
+            .map(|m| m.id)
+            .collect();
+        assert_eq!(ids, vec![3, 4, 5, 6]);
+    }
+
+    // Test that receivers of a fountain-coded broadcast can start at any point
+    #[test]
+    fn test_fountain() {
+        let mut encoder = MessageEncoder::new();
+        let mut messages: Vec<Vec<u8>> = MESSAGES.iter().map(|m| m.as_bytes().to_vec()).collect();
+        messages.push((0..MAX_FOUNTAIN_MESSAGE_LEN).map(|i| i as u8).collect());
+        for message in messages.iter() {
+            let stream = encoder.encode_fountain(message);
+            let header_len = if message.len() < 128 { 3 } else { 4 };
+            let blocks = ((header_len + message.len() + CHECKSUM_LEN) * 8 - 1) / BLOCK_BITS + 1;
+            for &start in [0, 1, blocks / 2, blocks, 500, 1000].iter() {
+                let mut decoder = MessageDecoder::new();
+                decoder.set_fountain(true);
+                let payloads: Vec<_> = stream.clone().skip(start).take(blocks + 10).collect();
+                let mut needed = None;
+                for (i, payload) in payloads.iter().enumerate() {
+                    decoder.push_payload(payload, 0.);
+                    if let Some(event) = decoder.poll_event() {
+                        assert_eq!(event, MessageEvent::Received(message.clone()));
+                        assert_eq!(needed, None);
+                        needed = Some(i + 1);
+                    }
+                }
+                let needed = needed.expect("message not received");
+                assert!(needed >= blocks);
+                if start == 0 {
+                    assert_eq!(needed, blocks);
+                }
+            }
+        }
+        // Packets of another message
+        let mut decoder = MessageDecoder::new();
+        decoder.set_fountain(true);
+        let other_payloads: Vec<_> = encoder.encode_fountain(&messages[5]).take(5).collect();
+        let payloads: Vec<_> = encoder
+            .encode_fountain(MESSAGES[0].as_bytes())
+            .take(10)
+            .collect();
+        assert!(receive_all(&mut decoder, &other_payloads).is_empty());
+        assert_eq!(
+            receive_all(&mut decoder, &payloads),
+            received(MESSAGES[0].as_bytes())
+        );
+        // A stray packet of another message doesn't interrupt the reception
+        let mut decoder = MessageDecoder::new();
+        decoder.set_fountain(true);
+        let stream = encoder.encode_fountain(MESSAGES[4].as_bytes());
+        let blocks = ((3 + MESSAGES[4].len() + CHECKSUM_LEN) * 8 - 1) / BLOCK_BITS + 1;
+        let mut payloads: Vec<_> = stream.take(blocks).collect();
+        payloads.insert(blocks / 2, other_payloads[0]);
+        assert!(receive_all(&mut decoder, &payloads[..blocks]).is_empty());
+        assert_eq!(
+            receive_all(&mut decoder, &payloads[blocks..]),
+            received(MESSAGES[4].as_bytes())
+        );
+        // The message that was updated least recently is dropped
+        let mut decoder = MessageDecoder::new();
+        decoder.set_fountain(true);
+        let first_payloads: Vec<_> = (0..=MAX_INCOMPLETE_MESSAGES)
+            .map(|_| encoder.encode_fountain(&messages[5]).next().unwrap())
+            .collect();
+        assert!(receive_all(&mut decoder, &first_payloads).is_empty());
+        let ids: Vec<usize> = (decoder.fountain_messages.iter())
+            .map(|&(id, _, _)| id)
+            .collect();
+        let expected_ids: Vec<usize> = (first_payloads[1..].iter())
+            .map(|payload| read_bits(&payload_to_bits(payload), 0, MESSAGE_ID_BITS))
+            .collect();
+        assert_eq!(ids, expected_ids);
+    }
+
+    // Test that forged, unauthenticated and replayed messages are rejected
+    #[test]
+    fn test_authentication() {
+        let mut encoder = MessageEncoder::new();
+        encoder.set_authentication(Some(b"secret"));
+        let mut decoder = MessageDecoder::new();
+        decoder.set_authentication(Some(b"secret"));
+        let rejected = |err| vec![MessageEvent::Rejected(err)];
+        for (i, message) in MESSAGES.iter().enumerate() {
+            assert_eq!(encoder.counter(), i as u32);
+            let payloads = encoder.encode(message.as_bytes());
+            let header_len = 3 + COUNTER_LEN + TAG_LEN + CHECKSUM_LEN;
+            assert_eq!(payloads.len(), payloads_len(header_len, message.len()));
+            assert_eq!(
+                receive_all(&mut decoder, &payloads),
+                received(message.as_bytes())
+            );
+            assert_eq!(decoder.last_counter(None), Some(i as u32));
+            // Replay
+            assert_eq!(
+                receive_all(&mut decoder, &payloads),
+                rejected(AuthError::Replayed)
+            );
+        }
+        // Fragmented messages
+        encoder.set_fragmented(true);
+        decoder.set_fragmented(true);
+        let payloads = encoder.encode(MESSAGES[0].as_bytes());
+        assert_eq!(
+            receive_all(&mut decoder, &payloads),
+            received(MESSAGES[0].as_bytes())
+        );
+        encoder.set_fragmented(false);
+        decoder.set_fragmented(false);
+        // Unauthenticated message
+        let mut other_encoder = MessageEncoder::new();
+        assert_eq!(
+            receive_all(&mut decoder, &other_encoder.encode(MESSAGES[0].as_bytes())),
+            rejected(AuthError::Unauthenticated)
+        );
+        // Wrong key
+        other_encoder.set_authentication(Some(b"other secret"));
+        other_encoder.set_counter(100);
+        assert_eq!(
+            receive_all(&mut decoder, &other_encoder.encode(MESSAGES[0].as_bytes())),
+            rejected(AuthError::InvalidTag)
+        );
+        // Without key, the authentication is ignored
+        let mut other_decoder = MessageDecoder::new();
+        assert_eq!(
+            receive_all(&mut other_decoder, &encoder.encode(MESSAGES[0].as_bytes())),
+            received(MESSAGES[0].as_bytes())
+        );
+        // The counter can be restored
+        decoder.set_last_counter(None, None);
+        encoder.set_counter(0);
+        assert_eq!(
+            receive_all(&mut decoder, &encoder.encode(MESSAGES[1].as_bytes())),
+            received(MESSAGES[1].as_bytes())
+        );
+    }
+
+    // Test that encrypted messages are only readable with the key
+    #[test]
+    fn test_encryption() {
+        let mut encoder = MessageEncoder::new();
+        encoder.set_authentication(Some(b"secret"));
+        encoder.set_encryption(true);
+        encoder.set_counter(0);
+        encoder.set_sender_id(0x1234_5678);
+        let mut decoder = MessageDecoder::new();
+        decoder.set_authentication(Some(b"secret"));
+        let message = b"WIFI:T:WPA;S:Kiosk;P:password;;";
+        let bytes = encoder.clone().frame(message);
+        let payloads = encoder.encode(message);
+        // The message follows the sender ID and the counter
+        let header_len = 3 + SENDER_ID_LEN + COUNTER_LEN;
+        assert_eq!(
+            bytes.len(),
+            header_len + message.len() + TAG_LEN + CHECKSUM_LEN
+        );
+        assert_ne!(&bytes[header_len..header_len + message.len()], &message[..]);
+        assert_eq!(receive_all(&mut decoder, &payloads), received(message));
+        // The same message is encrypted differently with the next counter
+        let next_payloads = encoder.encode(message);
+        assert_ne!(payloads[1..], next_payloads[1..]);
+        // Without key, encrypted messages are dropped
+        let mut other_decoder = MessageDecoder::new();
+        assert!(receive_all(&mut other_decoder, &next_payloads).is_empty());
+        // Wrong key
+        let mut other_decoder = MessageDecoder::new();
+        other_decoder.set_authentication(Some(b"other secret"));
+        assert_eq!(
+            receive_all(&mut other_decoder, &next_payloads),
+            vec![MessageEvent::Rejected(AuthError::InvalidTag)]
+        );
+        // Fountain mode
+        decoder.set_fountain(true);
+        let payloads: Vec<_> = encoder.encode_fountain(message).take(20).collect();
+        assert_eq!(receive_all(&mut decoder, &payloads), received(message));
+    }
+
+    // Test that encoders with the same key and counter don't reuse the nonce and that their
+    // counters are checked separately
+    #[test]
+    fn test_encryption_sender_ids() {
+        let message = MESSAGES[1].as_bytes();
+        let mut decoder = MessageDecoder::new();
+        decoder.set_authentication(Some(b"secret"));
+        let mut ciphertexts = Vec::new();
+        for &sender_id in [1, 2].iter() {
+            let mut encoder = MessageEncoder::new();
+            encoder.set_authentication(Some(b"secret"));
+            encoder.set_encryption(true);
+            encoder.set_counter(0);
+            encoder.set_sender_id(sender_id);
+            let bytes = encoder.clone().frame(message);
+            // Same key, counter and message
+            let start = 3 + SENDER_ID_LEN + COUNTER_LEN;
+            ciphertexts.push(bytes[start..start + message.len()].to_vec());
+            let payloads = encoder.encode(message);
+            assert_eq!(receive_all(&mut decoder, &payloads), received(message));
+            assert_eq!(
+                receive_all(&mut decoder, &payloads),
+                vec![MessageEvent::Rejected(AuthError::Replayed)]
+            );
+        }
+        assert_ne!(ciphertexts[0], ciphertexts[1]);
+        let expected: BTreeMap<_, _> = [(Some(1), 0), (Some(2), 0)].iter().cloned().collect();
+        assert_eq!(decoder.last_counters(), &expected);
+    }
+
+    // Test that encryption can't be used without authentication
+    #[test]
+    #[should_panic]
+    fn test_encryption_without_key() {
+        let mut encoder = MessageEncoder::new();
+        encoder.set_encryption(true);
+        encoder.set_counter(0);
+        encoder.set_sender_id(1);
+        encoder.encode(b"secret");
+    }
+
+    // Test that encryption requires an explicitly set counter
+    #[test]
+    #[should_panic]
+    fn test_encryption_without_counter() {
+        let mut encoder = MessageEncoder::new();
+        encoder.set_authentication(Some(b"secret"));
+        encoder.set_encryption(true);
+        encoder.set_sender_id(1);
+        encoder.encode(b"secret");
+    }
+
+    // Test that encryption requires a sender ID
+    #[test]
+    #[should_panic]
+    fn test_encryption_without_sender_id() {
+        let mut encoder = MessageEncoder::new();
+        encoder.set_authentication(Some(b"secret"));
+        encoder.set_encryption(true);
+        encoder.set_counter(0);
+        encoder.encode(b"secret");
+    }
+
+    // Test filtering of messages by the destination address
+    #[test]
+    fn test_addresses() {
+        let mut encoder = MessageEncoder::new();
+        encoder.set_address(Some(1));
+        let mut decoder = MessageDecoder::new();
+        decoder.set_address(Some(2));
+        let message = MESSAGES[0].as_bytes();
+        let received_from = |destination| {
+            vec![MessageEvent::ReceivedAddressed(
+                Addresses {
+                    source: 1,
+                    destination,
+                },
+                message.to_vec(),
+            )]
+        };
+        let payloads = encoder.encode(message);
+        assert_eq!(
+            payloads.len(),
+            payloads_len(3 + ADDRESSES_LEN + CHECKSUM_LEN, message.len())
+        );
+        assert_eq!(
+            receive_all(&mut decoder, &payloads),
+            received_from(BROADCAST_ADDRESS)
+        );
+        encoder.set_destination(2);
+        assert_eq!(
+            receive_all(&mut decoder, &encoder.encode(message)),
+            received_from(2)
+        );
+        encoder.set_destination(3);
+        let payloads = encoder.encode(message);
+        assert!(receive_all(&mut decoder, &payloads).is_empty());
+        // Without address, all messages are accepted
+        decoder.set_address(None);
+        assert_eq!(receive_all(&mut decoder, &payloads), received_from(3));
+        // Messages without addresses are accepted by everyone
+        decoder.set_address(Some(2));
+        encoder.set_address(None);
+        assert_eq!(
+            receive_all(&mut decoder, &encoder.encode(message)),
+            received(message)
+        );
+        // Together with authentication and encryption in fragmented mode
+        encoder.set_address(Some(1));
+        encoder.set_destination(2);
+        encoder.set_authentication(Some(b"secret"));
+        encoder.set_encryption(true);
+        encoder.set_counter(0);
+        encoder.set_sender_id(1);
+        encoder.set_fragmented(true);
+        decoder.set_authentication(Some(b"secret"));
+        decoder.set_fragmented(true);
+        assert_eq!(
+            receive_all(&mut decoder, &encoder.encode(message)),
+            received_from(2)
+        );
+        let max_len =
+            MAX_FRAGMENTED_MESSAGE_LEN - SENDER_ID_LEN - COUNTER_LEN - TAG_LEN - ADDRESSES_LEN;
+        let long_message = vec![0x5a; max_len];
+        assert_eq!(encoder.encode(&long_message).len(), MAX_FRAGMENTS);
+    }
+
+    // Test the events about the progress of messages and the configurable timeout
+    #[test]
+    fn test_progress_events() {
+        let mut encoder = MessageEncoder::new();
+        let mut decoder = MessageDecoder::new();
+        decoder.set_progress_events(true);
+        let message = vec![0x5a; 20];
+        let payloads = encoder.encode(&message);
+        assert_eq!(payloads.len(), 5);
+        let total = 3 + message.len() + CHECKSUM_LEN;
+        let mut expected = vec![MessageEvent::Started];
+        expected.extend((1..5).map(|i| MessageEvent::Progress(i * USABLE_PAYLOAD_BITS / 8, total)));
+        expected.extend(received(&message));
+        assert_eq!(receive_all(&mut decoder, &payloads), expected);
+        // Timeout
+        decoder.push_payload(&payloads[0], 0.);
+        decoder.push_payload(&payloads[1], 1.);
+        decoder.check_timeout(1. + MESSAGE_TIMEOUT as f64 * 0.9);
+        decoder.check_timeout(1. + MESSAGE_TIMEOUT as f64 * 1.1);
+        let events: Vec<_> = std::iter::from_fn(|| decoder.poll_event()).collect();
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[3], MessageEvent::TimedOut);
+        decoder.set_timeout(10.);
+        for (i, payload) in payloads.iter().enumerate() {
+            decoder.push_payload(payload, i as f64 * 9.);
+        }
+        assert_eq!(
+            std::iter::from_fn(|| decoder.poll_event()).last(),
+            received(&message).pop()
+        );
+        decoder.set_timeout(MESSAGE_TIMEOUT as f64);
+        // Invalid padding
+        encoder.set_checksum(false);
+        let mut payloads = encoder.encode(MESSAGES[3].as_bytes());
+        payloads[0][PAYLOAD_LEN - 2] = 1;
+        encoder.set_checksum(true);
+        assert_eq!(
+            receive_all(&mut decoder, &payloads),
+            vec![MessageEvent::Started, MessageEvent::InvalidPadding]
+        );
+        // Fragmented mode
+        encoder.set_fragmented(true);
+        decoder.set_fragmented(true);
+        let payloads = encoder.encode(&message);
+        assert_eq!(payloads.len(), 8);
+        let total = 8 * FRAGMENT_DATA_BITS / 8;
+        assert_eq!(
+            receive_all(&mut decoder, &payloads[..2]),
+            vec![
+                MessageEvent::Started,
+                MessageEvent::Progress(FRAGMENT_DATA_BITS / 8, total),
+                MessageEvent::Progress(2 * FRAGMENT_DATA_BITS / 8, total)
+            ]
+        );
+        decoder.check_timeout(MESSAGE_TIMEOUT as f64 * 1.1);
+        assert_eq!(decoder.poll_event(), Some(MessageEvent::TimedOut));
+        assert!(decoder.incomplete_messages().is_empty());
+        // Fountain mode
+        decoder.set_fountain(true);
+        let payloads: Vec<_> = encoder.encode_fountain(&message).take(8).collect();
+        let events = receive_all(&mut decoder, &payloads);
+        assert_eq!(events[0], MessageEvent::Started);
+        assert_eq!(events[1], MessageEvent::Progress(BLOCK_BITS / 8, total));
+        assert_eq!(events.last(), received(&message).last());
+    }
+}
@@ -0,0 +1,231 @@
+use std::error;
+use std::fmt;
+
+const BLOCK_LEN: usize = 64;
+pub(super) const HASH_LEN: usize = 32;
+
+#[rustfmt::skip]
+const ROUND_CONSTANTS: [u32; 64] = [
+    0x428a_2f98, 0x7137_4491, 0xb5c0_fbcf, 0xe9b5_dba5, 0x3956_c25b, 0x59f1_11f1, 0x923f_82a4,
+    0xab1c_5ed5, 0xd807_aa98, 0x1283_5b01, 0x2431_85be, 0x550c_7dc3, 0x72be_5d74, 0x80de_b1fe,
+    0x9bdc_06a7, 0xc19b_f174, 0xe49b_69c1, 0xefbe_4786, 0x0fc1_9dc6, 0x240c_a1cc, 0x2de9_2c6f,
+    0x4a74_84aa, 0x5cb0_a9dc, 0x76f9_88da, 0x983e_5152, 0xa831_c66d, 0xb003_27c8, 0xbf59_7fc7,
+    0xc6e0_0bf3, 0xd5a7_9147, 0x06ca_6351, 0x1429_2967, 0x27b7_0a85, 0x2e1b_2138, 0x4d2c_6dfc,
+    0x5338_0d13, 0x650a_7354, 0x766a_0abb, 0x81c2_c92e, 0x9272_2c85, 0xa2bf_e8a1, 0xa81a_664b,
+    0xc24b_8b70, 0xc76c_51a3, 0xd192_e819, 0xd699_0624, 0xf40e_3585, 0x106a_a070, 0x19a4_c116,
+    0x1e37_6c08, 0x2748_774c, 0x34b0_bcb5, 0x391c_0cb3, 0x4ed8_aa4a, 0x5b9c_ca4f, 0x682e_6ff3,
+    0x748f_82ee, 0x78a5_636f, 0x84c8_7814, 0x8cc7_0208, 0x90be_fffa, 0xa450_6ceb, 0xbef9_a3f7,
+    0xc671_78f2,
+];
+#[rustfmt::skip]
+const INITIAL_STATE: [u32; 8] = [
+    0x6a09_e667, 0xbb67_ae85, 0x3c6e_f372, 0xa54f_f53a, 0x510e_527f, 0x9b05_688c, 0x1f83_d9ab,
+    0x5be0_cd19,
+];
+
+// Reasons for rejecting a message, when authentication is enabled
+// (see method `MessageDecoder::set_authentication`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuthError {
+    // The message has no authentication tag
+    Unauthenticated,
+    // The authentication tag doesn't match, the message was forged or the key is different
+    InvalidTag,
+    // The counter is not greater than the counter of the last accepted message
+    Replayed,
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AuthError::Unauthenticated => write!(f, "message is not authenticated"),
+            AuthError::InvalidTag => write!(f, "invalid authentication tag"),
+            AuthError::Replayed => write!(f, "message was replayed"),
+        }
+    }
+}
+
+impl error::Error for AuthError {}
+
+// SHA-256 (FIPS 180-4)
+pub(super) fn sha256(chunks: &[&[u8]]) -> [u8; HASH_LEN] {
+    let mut state = INITIAL_STATE;
+    let mut block = Vec::with_capacity(BLOCK_LEN);
+    let mut len = 0u64;
+    for &chunk in chunks.iter() {
+        len += chunk.len() as u64;
+        for &byte in chunk.iter() {
+            block.push(byte);
+            if block.len() == BLOCK_LEN {
+                compress(&mut state, &block);
+                block.clear();
+            }
+        }
+    }
+    block.push(0x80);
+    if block.len() > BLOCK_LEN - 8 {
+        block.resize(BLOCK_LEN, 0);
+        compress(&mut state, &block);
+        block.clear();
+    }
+    block.resize(BLOCK_LEN - 8, 0);
+    block.extend((len * 8).to_be_bytes().iter());
+    compress(&mut state, &block);
+    let mut hash = [0; HASH_LEN];
+    for (bytes, word) in hash.chunks_mut(4).zip(state.iter()) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    hash
+}
+
+fn compress(state: &mut [u32; 8], block: &[u8]) {
+    let mut w = [0u32; 64];
+    for (i, bytes) in block.chunks(4).enumerate() {
+        w[i] = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+    for i in 16..64 {
+        let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+        let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16]
+            .wrapping_add(s0)
+            .wrapping_add(w[i - 7])
+            .wrapping_add(s1);
+    }
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+    for i in 0..64 {
+        let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let ch = (e & f) ^ (!e & g);
+        let t1 = h
+            .wrapping_add(s1)
+            .wrapping_add(ch)
+            .wrapping_add(ROUND_CONSTANTS[i])
+            .wrapping_add(w[i]);
+        let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+        let t2 = s0.wrapping_add(maj);
+        h = g;
+        g = f;
+        f = e;
+        e = d.wrapping_add(t1);
+        d = c;
+        c = b;
+        b = a;
+        a = t1.wrapping_add(t2);
+    }
+    for (word, value) in state.iter_mut().zip([a, b, c, d, e, f, g, h].iter()) {
+        *word = word.wrapping_add(*value);
+    }
+}
+
+// HMAC-SHA256 (RFC 2104)
+pub(super) fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; HASH_LEN] {
+    let mut key_block = [0u8; BLOCK_LEN];
+    if key.len() > BLOCK_LEN {
+        key_block[..HASH_LEN].copy_from_slice(&sha256(&[key]));
+    } else {
+        key_block[..key.len()].copy_from_slice(key);
+    }
+    let inner_pad: Vec<u8> = key_block.iter().map(|byte| byte ^ 0x36).collect();
+    let outer_pad: Vec<u8> = key_block.iter().map(|byte| byte ^ 0x5c).collect();
+    let inner_hash = sha256(&[&inner_pad, data]);
+    sha256(&[&outer_pad, &inner_hash])
+}
+
+// Compare without leaking the position of the first difference through the timing
+pub(super) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len()
+        && a.iter()
+            .zip(b.iter())
+            .fold(0, |diff, (x, y)| diff | (x ^ y))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    // Test vectors from FIPS 180-4
+    #[test]
+    fn test_sha256() {
+        let vectors: [(&[u8], &str); 3] = [
+            (
+                b"",
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            ),
+            (
+                b"abc",
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            ),
+            (
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq",
+                "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1",
+            ),
+        ];
+        for &(data, hash) in vectors.iter() {
+            assert_eq!(sha256(&[data]).to_vec(), from_hex(hash));
+            // Split into chunks
+            let (a, b) = data.split_at(data.len() / 3);
+            assert_eq!(sha256(&[a, b]).to_vec(), from_hex(hash));
+        }
+        let million_a = vec![b'a'; 1_000_000];
+        assert_eq!(
+            sha256(&[&million_a]).to_vec(),
+            from_hex("cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0")
+        );
+    }
+
+    // Test vectors from RFC 4231
+    #[test]
+    fn test_hmac_sha256() {
+        let long_key = vec![0xaa; 131];
+        let vectors: [(Vec<u8>, Vec<u8>, &str); 6] = [
+            (
+                vec![0x0b; 20],
+                b"Hi There".to_vec(),
+                "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7",
+            ),
+            (
+                b"Jefe".to_vec(),
+                b"what do ya want for nothing?".to_vec(),
+                "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            ),
+            (
+                vec![0xaa; 20],
+                vec![0xdd; 50],
+                "773ea91e36800e46854db8ebd09181a72959098b3ef8c122d9635514ced565fe",
+            ),
+            (
+                from_hex("0102030405060708090a0b0c0d0e0f10111213141516171819"),
+                vec![0xcd; 50],
+                "82558a389a443c0ea4cc819899f2083a85f0faa3e578f8077a2e3ff46729665b",
+            ),
+            (
+                long_key.clone(),
+                b"Test Using Larger Than Block-Size Key - Hash Key First".to_vec(),
+                "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54",
+            ),
+            (
+                long_key,
+                b"This is a test using a larger than block-size key and a larger than block-size \
+                  data. The key needs to be hashed before being used by the HMAC algorithm."
+                    .to_vec(),
+                "9b09ffa71b942fcb27635fbcd5b0e944bfdc63644f0713938a7f51535c3a35e2",
+            ),
+        ];
+        for (key, data, mac) in vectors.iter() {
+            assert_eq!(hmac_sha256(key, data).to_vec(), from_hex(mac));
+        }
+        // Test case 5 with truncation to 128 bits
+        assert_eq!(
+            hmac_sha256(&[0x0c; 20], b"Test With Truncation")[..16].to_vec(),
+            from_hex("a3b6167473100ee06e0c796c2955552b")
+        );
+    }
+}
@@ -0,0 +1,139 @@
+use super::auth::hmac_sha256;
+use super::{COUNTER_LEN, SENDER_ID_LEN};
+
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+const BLOCK_LEN: usize = 64;
+// "expand 32-byte k"
+const CONSTANTS: [u32; 4] = [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574];
+// The encryption key is derived from the shared key, the authentication uses the shared key
+// directly. Authenticated data starts with a zero byte and never equals this label.
+const KEY_LABEL: &[u8] = b"audio-barcode encryption";
+
+// Encrypt or decrypt the `data` of the message with the `sender_id` and the `counter`.
+// Both form the nonce: the counter is unique for each message of a sender and the random ID
+// of the sender is unique for each sender and session with the same key.
+pub(super) fn encrypt(shared_key: &[u8], sender_id: u32, counter: u32, data: &mut [u8]) {
+    let key = hmac_sha256(shared_key, KEY_LABEL);
+    let mut nonce = [0; NONCE_LEN];
+    let counter_start = NONCE_LEN - COUNTER_LEN;
+    nonce[counter_start - SENDER_ID_LEN..counter_start].copy_from_slice(&sender_id.to_be_bytes());
+    nonce[counter_start..].copy_from_slice(&counter.to_be_bytes());
+    chacha20(&key, &nonce, 0, data);
+}
+
+// Encrypt or decrypt `data` in place with ChaCha20 (RFC 8439), starting with the block `counter`
+fn chacha20(key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN], counter: u32, data: &mut [u8]) {
+    for (i, chunk) in data.chunks_mut(BLOCK_LEN).enumerate() {
+        let keystream = block(key, nonce, counter.wrapping_add(i as u32));
+        for (byte, key_byte) in chunk.iter_mut().zip(keystream.iter()) {
+            *byte ^= key_byte;
+        }
+    }
+}
+
+fn block(key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN], counter: u32) -> [u8; BLOCK_LEN] {
+    let mut initial_state = [0u32; 16];
+    initial_state[..4].copy_from_slice(&CONSTANTS);
+    for (word, bytes) in initial_state[4..12].iter_mut().zip(key.chunks(4)) {
+        *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+    initial_state[12] = counter;
+    for (word, bytes) in initial_state[13..].iter_mut().zip(nonce.chunks(4)) {
+        *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+    let mut state = initial_state;
+    for _ in 0..10 {
+        // Column rounds
+        quarter_round(&mut state, 0, 4, 8, 12);
+        quarter_round(&mut state, 1, 5, 9, 13);
+        quarter_round(&mut state, 2, 6, 10, 14);
+        quarter_round(&mut state, 3, 7, 11, 15);
+        // Diagonal rounds
+        quarter_round(&mut state, 0, 5, 10, 15);
+        quarter_round(&mut state, 1, 6, 11, 12);
+        quarter_round(&mut state, 2, 7, 8, 13);
+        quarter_round(&mut state, 3, 4, 9, 14);
+    }
+    let mut keystream = [0; BLOCK_LEN];
+    for ((bytes, word), initial_word) in (keystream.chunks_mut(4))
+        .zip(state.iter())
+        .zip(initial_state.iter())
+    {
+        bytes.copy_from_slice(&word.wrapping_add(*initial_word).to_le_bytes());
+    }
+    keystream
+}
+
+fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        let hex: String = hex.split_whitespace().collect();
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn sequential_key() -> [u8; KEY_LEN] {
+        let mut key = [0; KEY_LEN];
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        key
+    }
+
+    // Test the block function with the vectors from RFC 8439 (sections 2.3.2 and A.1)
+    #[test]
+    fn test_block() {
+        let nonce = [0, 0, 0, 0x09, 0, 0, 0, 0x4a, 0, 0, 0, 0];
+        assert_eq!(
+            block(&sequential_key(), &nonce, 1).to_vec(),
+            from_hex(
+                "10f1e7e4d13b5915500fdd1fa32071c4c7d1f4c733c068030422aa9ac3d46c4e
+                 d2826446079faa0914c2d705d98b02a2b5129cd1de164eb9cbd083e8a2503c4e"
+            )
+        );
+        assert_eq!(
+            block(&[0; KEY_LEN], &[0; NONCE_LEN], 0).to_vec(),
+            from_hex(
+                "76b8e0ada0f13d90405d6ae55386bd28bdd219b8a08ded1aa836efcc8b770dc7
+                 da41597c5157488d7724e03fb8d84a376a43b8f41518a11cc387b669b2ee6586"
+            )
+        );
+    }
+
+    // Test encryption with the vector from RFC 8439 (section 2.4.2)
+    #[test]
+    fn test_chacha20() {
+        let nonce = [0, 0, 0, 0, 0, 0, 0, 0x4a, 0, 0, 0, 0];
+        let plaintext = b"Ladies and Gentlemen of the class of '99: If I could offer you only one \
+                          tip for the future, sunscreen would be it.";
+        let mut data = plaintext.to_vec();
+        chacha20(&sequential_key(), &nonce, 1, &mut data);
+        assert_eq!(
+            data,
+            from_hex(
+                "6e2e359a2568f98041ba0728dd0d6981e97e7aec1d4360c20a27afccfd9fae0b
+                 f91b65c5524733ab8f593dabcd62b3571639d624e65152ab8f530c359f0861d8
+                 07ca0dbf500d6a6156a38e088a22b65e52bc514d16ccf806818ce91ab7793736
+                 5af90bbf74a35be6b40b8eedf2785e42874d"
+            )
+        );
+        chacha20(&sequential_key(), &nonce, 1, &mut data);
+        assert_eq!(data, plaintext.to_vec());
+    }
+}
@@ -0,0 +1,169 @@
+use super::{
+    bits_to_payload, bytes_to_bits, read_bits, write_bits, FRAGMENT_DATA_BITS, MESSAGE_ID_BITS,
+    PAYLOAD_BITS,
+};
+use crate::PAYLOAD_LEN;
+
+// Each payload starts with the message ID, the number of source blocks minus one and the
+// sequence number of the packet. The remaining bits contain the encoded block.
+pub(super) const BLOCK_COUNT_BITS: usize = 6;
+pub(super) const SEQUENCE_BITS: usize = 10;
+pub(super) const BLOCK_BITS: usize = FRAGMENT_DATA_BITS;
+pub(super) const MAX_BLOCKS: usize = 1 << BLOCK_COUNT_BITS;
+const SEQUENCES: usize = 1 << SEQUENCE_BITS;
+
+// Endless stream of fountain-coded payloads of a message (see `MessageEncoder::encode_fountain`).
+// The first packets contain the source blocks, the following packets random combinations.
+#[derive(Clone, Debug)]
+pub struct FountainStream {
+    id: usize,
+    blocks: Vec<u32>,
+    sequence: usize,
+}
+
+impl FountainStream {
+    pub(super) fn new(id: usize, bytes: &[u8]) -> Self {
+        let blocks: Vec<u32> = bytes_to_bits(bytes)
+            .chunks(BLOCK_BITS)
+            .map(|bits| {
+                let value = read_bits(bits, 0, bits.len());
+                (value << (BLOCK_BITS - bits.len())) as u32
+            })
+            .collect();
+        assert!(blocks.len() <= MAX_BLOCKS);
+        Self {
+            id,
+            blocks,
+            sequence: 0,
+        }
+    }
+}
+
+impl Iterator for FountainStream {
+    type Item = [u8; PAYLOAD_LEN];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let count = self.blocks.len();
+        let row = combination(self.id, count, self.sequence);
+        let block = (self.blocks.iter().enumerate())
+            .filter(|&(i, _)| row & (1 << i) != 0)
+            .fold(0, |block, (_, &source)| block ^ source);
+        let mut bits = Vec::with_capacity(PAYLOAD_BITS);
+        write_bits(&mut bits, self.id, MESSAGE_ID_BITS);
+        write_bits(&mut bits, count - 1, BLOCK_COUNT_BITS);
+        write_bits(&mut bits, self.sequence, SEQUENCE_BITS);
+        write_bits(&mut bits, block as usize, BLOCK_BITS);
+        self.sequence = (self.sequence + 1) % SEQUENCES;
+        Some(bits_to_payload(&bits))
+    }
+}
+
+// Get the source blocks that are combined in the packet with the sequence number `sequence`
+pub(super) fn combination(id: usize, count: usize, sequence: usize) -> u64 {
+    if sequence < count {
+        return 1 << sequence;
+    }
+    let mask = if count == 64 { !0 } else { (1 << count) - 1 };
+    let seed =
+        (id << (BLOCK_COUNT_BITS + SEQUENCE_BITS)) | ((count - 1) << SEQUENCE_BITS) | sequence;
+    match splitmix64(seed as u64) & mask {
+        0 => 1 << (sequence % count),
+        row => row,
+    }
+}
+
+fn splitmix64(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// Incremental Gaussian elimination over GF(2)
+#[derive(Clone, Debug)]
+pub(super) struct FountainSolver {
+    // Rows with the lowest set bit in the column of their index
+    pivots: Vec<Option<(u64, u32)>>,
+    rank: usize,
+}
+
+impl FountainSolver {
+    pub(super) fn new(count: usize) -> Self {
+        Self {
+            pivots: vec![None; count],
+            rank: 0,
+        }
+    }
+
+    pub(super) fn block_count(&self) -> usize {
+        self.pivots.len()
+    }
+
+    // Number of linearly independent packets
+    pub(super) fn rank(&self) -> usize {
+        self.rank
+    }
+
+    pub(super) fn push(&mut self, mut row: u64, mut block: u32) {
+        while row != 0 {
+            let column = row.trailing_zeros() as usize;
+            match self.pivots[column] {
+                Some((pivot_row, pivot_block)) => {
+                    row ^= pivot_row;
+                    block ^= pivot_block;
+                }
+                None => {
+                    self.pivots[column] = Some((row, block));
+                    self.rank += 1;
+                    return;
+                }
+            }
+        }
+    }
+
+    // Get the source blocks, when enough packets were received
+    pub(super) fn solve(&self) -> Option<Vec<u32>> {
+        if self.rank < self.pivots.len() {
+            return None;
+        }
+        let mut blocks = vec![0; self.pivots.len()];
+        for column in (0..self.pivots.len()).rev() {
+            let (row, block) = self.pivots[column]?;
+            blocks[column] = ((column + 1)..self.pivots.len())
+                .filter(|&i| row & (1 << i) != 0)
+                .fold(block, |block, i| block ^ blocks[i]);
+        }
+        Some(blocks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test that the source blocks are recovered from any packets with little overhead
+    #[test]
+    fn test_solver() {
+        let count = 40;
+        let blocks: Vec<u32> = (0..count)
+            .map(|i| (i * 0x0123_4567 % (1 << 30)) as u32)
+            .collect();
+        let mut overhead = 0;
+        for start in 0..100 {
+            let mut solver = FountainSolver::new(count);
+            let mut sequence = start * 10;
+            while solver.solve().is_none() {
+                let row = combination(3, count, sequence);
+                let block = (0..count)
+                    .filter(|&i| row & (1 << i) != 0)
+                    .fold(0, |block, i| block ^ blocks[i]);
+                solver.push(row, block);
+                sequence = (sequence + 1) % SEQUENCES;
+            }
+            assert_eq!(solver.solve(), Some(blocks.clone()));
+            overhead += (sequence + SEQUENCES - start * 10) % SEQUENCES - count;
+        }
+        // About 1.6 additional packets are needed on average
+        assert!(overhead <= 100 * 3, "overhead {}", overhead);
+    }
+}
@@ -0,0 +1,217 @@
+use std::error;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use super::text::{decode_text, encode_text_compact};
+use super::{read_varint, write_varint};
+
+// Types of records that are defined by applications
+pub const APPLICATION_RECORD_TYPES: RangeInclusive<u8> = 0x80..=0xff;
+
+const URL_RECORD: u8 = 0x01;
+const TEXT_RECORD: u8 = 0x02;
+const KEY_VALUE_RECORD: u8 = 0x03;
+const ID_RECORD: u8 = 0x04;
+const BINARY_RECORD: u8 = 0x05;
+
+// Typed content of a message. The first byte contains the type, followed by the payload.
+// Text and URLs are encoded with function `encode_text_compact`.
+// Key/value pairs are encoded as the lengths (varint) and the UTF-8 bytes of each key and value.
+// IDs are encoded as 8 bytes (big-endian).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Record {
+    Url(String),
+    Text(String),
+    KeyValue(Vec<(String, String)>),
+    Id(u64),
+    Binary(Vec<u8>),
+    // The type must be in the range `APPLICATION_RECORD_TYPES`
+    Application(u8, Vec<u8>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RecordError {
+    UnknownType(u8),
+    InvalidData,
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecordError::UnknownType(record_type) => {
+                write!(f, "unknown record type {}", record_type)
+            }
+            RecordError::InvalidData => write!(f, "invalid record"),
+        }
+    }
+}
+
+impl error::Error for RecordError {}
+
+impl Record {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        match self {
+            Record::Url(url) => {
+                bytes.push(URL_RECORD);
+                bytes.extend(encode_text_compact(url));
+            }
+            Record::Text(text) => {
+                bytes.push(TEXT_RECORD);
+                bytes.extend(encode_text_compact(text));
+            }
+            Record::KeyValue(pairs) => {
+                bytes.push(KEY_VALUE_RECORD);
+                for (key, value) in pairs.iter() {
+                    for s in [key, value].iter() {
+                        write_varint(&mut bytes, s.len());
+                        bytes.extend(s.as_bytes());
+                    }
+                }
+            }
+            Record::Id(id) => {
+                bytes.push(ID_RECORD);
+                bytes.extend(id.to_be_bytes().iter());
+            }
+            Record::Binary(data) => {
+                bytes.push(BINARY_RECORD);
+                bytes.extend(data);
+            }
+            Record::Application(record_type, data) => {
+                assert!(
+                    APPLICATION_RECORD_TYPES.contains(record_type),
+                    "record type must be in the range {:?} but is {}",
+                    APPLICATION_RECORD_TYPES,
+                    record_type
+                );
+                bytes.push(*record_type);
+                bytes.extend(data);
+            }
+        }
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, RecordError> {
+        let (&record_type, payload) = bytes.split_first().ok_or(RecordError::InvalidData)?;
+        match record_type {
+            URL_RECORD => decode_text(payload)
+                .map(Record::Url)
+                .map_err(|_| RecordError::InvalidData),
+            TEXT_RECORD => decode_text(payload)
+                .map(Record::Text)
+                .map_err(|_| RecordError::InvalidData),
+            KEY_VALUE_RECORD => decode_key_value(payload).map(Record::KeyValue),
+            ID_RECORD => {
+                let mut id = [0; 8];
+                if payload.len() != id.len() {
+                    return Err(RecordError::InvalidData);
+                }
+                id.copy_from_slice(payload);
+                Ok(Record::Id(u64::from_be_bytes(id)))
+            }
+            BINARY_RECORD => Ok(Record::Binary(payload.to_vec())),
+            _ if APPLICATION_RECORD_TYPES.contains(&record_type) => {
+                Ok(Record::Application(record_type, payload.to_vec()))
+            }
+            _ => Err(RecordError::UnknownType(record_type)),
+        }
+    }
+}
+
+fn decode_key_value(mut payload: &[u8]) -> Result<Vec<(String, String)>, RecordError> {
+    let mut strings = Vec::new();
+    while !payload.is_empty() {
+        let (len, varint_len) = match read_varint(payload) {
+            Ok(Some(result)) => result,
+            _ => return Err(RecordError::InvalidData),
+        };
+        // The length can be `u32::MAX`, which overflows on 32-bit targets
+        let end = varint_len
+            .checked_add(len)
+            .ok_or(RecordError::InvalidData)?;
+        let s = payload
+            .get(varint_len..end)
+            .and_then(|bytes| String::from_utf8(bytes.to_vec()).ok())
+            .ok_or(RecordError::InvalidData)?;
+        strings.push(s);
+        payload = &payload[end..];
+    }
+    if strings.len() % 2 != 0 {
+        return Err(RecordError::InvalidData);
+    }
+    let mut pairs = Vec::with_capacity(strings.len() / 2);
+    let mut strings = strings.into_iter();
+    while let (Some(key), Some(value)) = (strings.next(), strings.next()) {
+        pairs.push((key, value));
+    }
+    Ok(pairs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test encoding and decoding of all record types
+    #[test]
+    fn test_records() {
+        let records = [
+            (Record::Url("https://www.example.com/".to_owned()), 1 + 10),
+            (Record::Text("hello world".to_owned()), 1 + 8),
+            (Record::Text("Grüße".to_owned()), 1 + 8),
+            (
+                Record::KeyValue(vec![
+                    ("ssid".to_owned(), "Kiosk".to_owned()),
+                    ("".to_owned(), "".to_owned()),
+                ]),
+                1 + 5 + 6 + 1 + 1,
+            ),
+            (Record::KeyValue(Vec::new()), 1),
+            (Record::Id(0x0123_4567_89ab_cdef), 1 + 8),
+            (Record::Binary(vec![0, 1, 2]), 1 + 3),
+            (Record::Application(0x80, vec![0xff]), 1 + 1),
+            (Record::Application(0xff, Vec::new()), 1),
+        ];
+        for (record, len) in records.iter() {
+            let bytes = record.encode();
+            assert_eq!(bytes.len(), *len, "{:?}", record);
+            assert_eq!(Record::decode(&bytes).as_ref(), Ok(record));
+        }
+    }
+
+    // Test that unknown types and invalid payloads are rejected
+    #[test]
+    fn test_invalid_records() {
+        assert_eq!(Record::decode(&[]), Err(RecordError::InvalidData));
+        assert_eq!(Record::decode(&[0x00]), Err(RecordError::UnknownType(0x00)));
+        assert_eq!(
+            Record::decode(&[0x7f, 1]),
+            Err(RecordError::UnknownType(0x7f))
+        );
+        assert_eq!(
+            Record::decode(&[ID_RECORD, 1]),
+            Err(RecordError::InvalidData)
+        );
+        assert_eq!(
+            Record::decode(&[TEXT_RECORD]),
+            Err(RecordError::InvalidData)
+        );
+        // Missing value, truncated string and invalid UTF-8
+        for payload in [&[1, b'a'][..], &[2, b'a'], &[1, 0xff, 0]].iter() {
+            let mut bytes = vec![KEY_VALUE_RECORD];
+            bytes.extend(payload.iter());
+            assert_eq!(Record::decode(&bytes), Err(RecordError::InvalidData));
+        }
+        // Maximal length
+        assert_eq!(
+            Record::decode(&[KEY_VALUE_RECORD, 0xff, 0xff, 0xff, 0xff, 0x0f, b'a']),
+            Err(RecordError::InvalidData)
+        );
+    }
+
+    // Test that application records need a type from the application range
+    #[test]
+    #[should_panic]
+    fn test_invalid_application_type() {
+        Record::Application(0x7f, Vec::new()).encode();
+    }
+}
@@ -0,0 +1,275 @@
+use std::error;
+use std::fmt;
+
+// Characters of the 5-bit and 6-bit encodings, the index is the value of a character
+const LOWERCASE_CHARSET: &str = "abcdefghijklmnopqrstuvwxyz .,?!-";
+const UPPERCASE_CHARSET: &str =
+    "ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789 -._~:/?#[]@!$&'()*+,;=%\"<>\n";
+// Common parts of URLs. In the URL encoding, bytes from 0x80 are indices into the dictionary
+// and other bytes are ASCII characters.
+const URL_DICTIONARY: [&str; 32] = [
+    "https://www.",
+    "http://www.",
+    "https://",
+    "http://",
+    "www.",
+    ".com/",
+    ".com",
+    ".org/",
+    ".org",
+    ".net/",
+    ".net",
+    ".de/",
+    ".de",
+    ".io/",
+    ".io",
+    ".html",
+    ".php",
+    "/index",
+    "?id=",
+    "github.com/",
+    "youtube.com/watch?v=",
+    "google.com/",
+    "WIFI:T:WPA;S:",
+    "WIFI:T:nopass;S:",
+    ";P:",
+    ";;",
+    "mailto:",
+    "tel:",
+    "geo:",
+    "?q=",
+    "&",
+    "/",
+];
+const URL_DICTIONARY_START: u8 = 0x80;
+// The header byte contains the encoding in the lower 4 bits and the number of padding bits
+// in the last byte in the upper 4 bits
+const ENCODING_MASK: u8 = 0x0f;
+const PADDING_SHIFT: u8 = 4;
+
+// Encodings of text in messages (see function `encode_text`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextEncoding {
+    // Any text
+    Utf8,
+    // 5 bits per character: `a-z`, space and `.,?!-`
+    Lowercase,
+    // 6 bits per character: `A-Z`, `0-9`, space, the characters that are allowed in URLs,
+    // `"<>` and newline
+    Uppercase,
+    // ASCII text with a static dictionary of common parts of URLs
+    Url,
+}
+
+const ENCODINGS: [TextEncoding; 4] = [
+    TextEncoding::Utf8,
+    TextEncoding::Lowercase,
+    TextEncoding::Uppercase,
+    TextEncoding::Url,
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TextError {
+    // The character is not supported by the encoding
+    UnsupportedCharacter(char),
+    UnknownEncoding(u8),
+    InvalidData,
+}
+
+impl fmt::Display for TextError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TextError::UnsupportedCharacter(c) => {
+                write!(f, "character {:?} is not supported by the encoding", c)
+            }
+            TextError::UnknownEncoding(header) => write!(f, "unknown text encoding {}", header),
+            TextError::InvalidData => write!(f, "invalid encoded text"),
+        }
+    }
+}
+
+impl error::Error for TextError {}
+
+// Encode `text` for a message. The first byte marks the encoding for function `decode_text`.
+pub fn encode_text(text: &str, encoding: TextEncoding) -> Result<Vec<u8>, TextError> {
+    let tag = ENCODINGS.iter().position(|&e| e == encoding).unwrap() as u8;
+    let mut bytes = vec![tag];
+    match encoding {
+        TextEncoding::Utf8 => bytes.extend(text.as_bytes()),
+        TextEncoding::Lowercase => {
+            let padding = pack(&mut bytes, text, LOWERCASE_CHARSET, 5)?;
+            bytes[0] |= padding << PADDING_SHIFT;
+        }
+        TextEncoding::Uppercase => {
+            let padding = pack(&mut bytes, text, UPPERCASE_CHARSET, 6)?;
+            bytes[0] |= padding << PADDING_SHIFT;
+        }
+        TextEncoding::Url => compress_url(&mut bytes, text)?,
+    }
+    Ok(bytes)
+}
+
+// Encode `text` with the encoding that gives the shortest result
+pub fn encode_text_compact(text: &str) -> Vec<u8> {
+    ENCODINGS
+        .iter()
+        .filter_map(|&encoding| encode_text(text, encoding).ok())
+        .min_by_key(Vec::len)
+        .unwrap()
+}
+
+// Decode text from function `encode_text`
+pub fn decode_text(bytes: &[u8]) -> Result<String, TextError> {
+    let (&header, data) = bytes.split_first().ok_or(TextError::InvalidData)?;
+    let padding = (header >> PADDING_SHIFT) as usize;
+    let encoding = *ENCODINGS
+        .get((header & ENCODING_MASK) as usize)
+        .ok_or(TextError::UnknownEncoding(header))?;
+    if padding > 0 && !(encoding == TextEncoding::Lowercase || encoding == TextEncoding::Uppercase)
+    {
+        return Err(TextError::UnknownEncoding(header));
+    }
+    match encoding {
+        TextEncoding::Utf8 => String::from_utf8(data.to_vec()).map_err(|_| TextError::InvalidData),
+        TextEncoding::Lowercase => unpack(data, padding, LOWERCASE_CHARSET, 5),
+        TextEncoding::Uppercase => unpack(data, padding, UPPERCASE_CHARSET, 6),
+        TextEncoding::Url => decompress_url(data),
+    }
+}
+
+// Returns the number of padding bits in the last byte
+fn pack(bytes: &mut Vec<u8>, text: &str, charset: &str, bits: usize) -> Result<u8, TextError> {
+    let mut buffer = 0u32;
+    let mut buffer_len = 0;
+    for c in text.chars() {
+        let value = charset
+            .chars()
+            .position(|charset_c| charset_c == c)
+            .ok_or(TextError::UnsupportedCharacter(c))?;
+        buffer = (buffer << bits) | (value as u32);
+        buffer_len += bits;
+        while buffer_len >= 8 {
+            buffer_len -= 8;
+            bytes.push((buffer >> buffer_len) as u8);
+        }
+    }
+    if buffer_len == 0 {
+        return Ok(0);
+    }
+    bytes.push((buffer << (8 - buffer_len)) as u8);
+    Ok((8 - buffer_len) as u8)
+}
+
+fn unpack(data: &[u8], padding: usize, charset: &str, bits: usize) -> Result<String, TextError> {
+    let total_bits = (data.len() * 8)
+        .checked_sub(padding)
+        .ok_or(TextError::InvalidData)?;
+    if padding >= 8 || total_bits % bits != 0 {
+        return Err(TextError::InvalidData);
+    }
+    let charset: Vec<char> = charset.chars().collect();
+    let mut text = String::with_capacity(total_bits / bits);
+    for i in 0..(total_bits / bits) {
+        let value = (0..bits).fold(0, |value, j| {
+            let bit = i * bits + j;
+            (value << 1) | ((data[bit / 8] >> (7 - bit % 8)) & 1) as usize
+        });
+        text.push(charset[value]);
+    }
+    Ok(text)
+}
+
+fn compress_url(bytes: &mut Vec<u8>, text: &str) -> Result<(), TextError> {
+    if let Some(c) = text.chars().find(|c| !c.is_ascii()) {
+        return Err(TextError::UnsupportedCharacter(c));
+    }
+    let mut rest = text;
+    while !rest.is_empty() {
+        // Longest match
+        let entry = (URL_DICTIONARY.iter().enumerate())
+            .filter(|(_, entry)| rest.starts_with(*entry))
+            .max_by_key(|(_, entry)| entry.len());
+        match entry {
+            Some((i, entry)) => {
+                bytes.push(URL_DICTIONARY_START + i as u8);
+                rest = &rest[entry.len()..];
+            }
+            None => {
+                bytes.push(rest.as_bytes()[0]);
+                rest = &rest[1..];
+            }
+        }
+    }
+    Ok(())
+}
+
+fn decompress_url(data: &[u8]) -> Result<String, TextError> {
+    let mut text = String::new();
+    for &byte in data.iter() {
+        if byte < URL_DICTIONARY_START {
+            text.push(byte as char);
+        } else {
+            let entry = URL_DICTIONARY
+                .get((byte - URL_DICTIONARY_START) as usize)
+                .ok_or(TextError::InvalidData)?;
+            text.push_str(entry);
+        }
+    }
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test that the encodings are lossless and compact
+    #[test]
+    fn test_encodings() {
+        assert_eq!(LOWERCASE_CHARSET.chars().count(), 32);
+        assert_eq!(UPPERCASE_CHARSET.chars().count(), 64);
+        let texts: [(&str, TextEncoding, usize); 10] = [
+            ("", TextEncoding::Utf8, 1),
+            ("😀 Grüße", TextEncoding::Utf8, 1 + 12),
+            ("", TextEncoding::Lowercase, 1),
+            ("hello world", TextEncoding::Lowercase, 1 + 7),
+            ("hello, world!", TextEncoding::Lowercase, 1 + 9),
+            ("abcdefgh", TextEncoding::Lowercase, 1 + 5),
+            ("HELLO WORLD 42", TextEncoding::Uppercase, 1 + 11),
+            ("ABCD", TextEncoding::Uppercase, 1 + 3),
+            ("https://www.example.com/", TextEncoding::Url, 1 + 9),
+            ("WIFI:T:WPA;S:Kiosk;P:secret;;", TextEncoding::Url, 1 + 14),
+        ];
+        for &(text, encoding, len) in texts.iter() {
+            let bytes = encode_text(text, encoding).unwrap();
+            assert_eq!(bytes.len(), len, "{:?}", text);
+            assert_eq!(decode_text(&bytes), Ok(text.to_owned()));
+            assert!(encode_text_compact(text).len() <= len);
+            assert_eq!(decode_text(&encode_text_compact(text)), Ok(text.to_owned()));
+        }
+        assert_eq!(encode_text_compact("hello world")[0] & ENCODING_MASK, 1);
+    }
+
+    // Test that unsupported characters and invalid data are rejected
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            encode_text("Hello", TextEncoding::Lowercase),
+            Err(TextError::UnsupportedCharacter('H'))
+        );
+        assert_eq!(
+            encode_text("hello", TextEncoding::Uppercase),
+            Err(TextError::UnsupportedCharacter('h'))
+        );
+        assert_eq!(
+            encode_text("https://ä.de", TextEncoding::Url),
+            Err(TextError::UnsupportedCharacter('ä'))
+        );
+        assert_eq!(decode_text(&[]), Err(TextError::InvalidData));
+        assert_eq!(decode_text(&[0x04]), Err(TextError::UnknownEncoding(0x04)));
+        assert_eq!(decode_text(&[0x30]), Err(TextError::UnknownEncoding(0x30)));
+        assert_eq!(decode_text(&[0x00, 0xff]), Err(TextError::InvalidData));
+        assert_eq!(decode_text(&[0x03, 0xff]), Err(TextError::InvalidData));
+        // Padding that isn't a multiple of the character size
+        assert_eq!(decode_text(&[0x41, 0x00]), Err(TextError::InvalidData));
+    }
+}
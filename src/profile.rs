@@ -0,0 +1,187 @@
+use std::error;
+use std::fmt;
+
+use super::{ATTACK_TIME, BEEP_TIME, RELEASE_TIME, SYMBOL_COUNT};
+
+const SEMITONE: f32 = 1.059_463_1;
+const BASE_FREQ: f32 = 1760.0;
+const TELEPHONE_BASE_FREQ: f32 = 500.0;
+const TELEPHONE_FREQ_STEP: f32 = 85.0;
+const TELEPHONE_BEEP_TIME: f32 = 0.12;
+const TELEPHONE_ATTACK_TIME: f32 = 0.015;
+const TELEPHONE_RELEASE_TIME: f32 = 0.015;
+
+// Frequencies and timings of the beeps
+// The sender and the receiver must use the same profile.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Profile {
+    // Frequency of the beep for each symbol
+    frequencies: [f32; SYMBOL_COUNT],
+    beep_time: f32,
+    attack_time: f32,
+    release_time: f32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProfileError {
+    // The frequency of the symbol is not positive or is used for another symbol
+    InvalidFrequency(usize),
+    // The times are not positive or the attack and the release don't fit into the beep
+    InvalidTiming,
+}
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProfileError::InvalidFrequency(symbol) => {
+                write!(f, "invalid frequency of symbol {}", symbol)
+            }
+            ProfileError::InvalidTiming => write!(f, "invalid beep timing"),
+        }
+    }
+}
+
+impl error::Error for ProfileError {}
+
+impl Default for Profile {
+    // Semitones starting at 1760 Hz, requires a sample rate of atleast 21.1 kHz
+    fn default() -> Self {
+        let mut frequencies = [0.; SYMBOL_COUNT];
+        for (i, frequency) in frequencies.iter_mut().enumerate() {
+            *frequency = BASE_FREQ * SEMITONE.powi(i as i32);
+        }
+        Self {
+            frequencies,
+            beep_time: BEEP_TIME,
+            attack_time: ATTACK_TIME,
+            release_time: RELEASE_TIME,
+        }
+    }
+}
+
+impl Profile {
+    // Create a profile with the beep frequency of each symbol and the timings in seconds
+    pub fn new(
+        frequencies: [f32; SYMBOL_COUNT],
+        beep_time: f32,
+        attack_time: f32,
+        release_time: f32,
+    ) -> Result<Self, ProfileError> {
+        // Comparisons with NaN are false
+        for (symbol, &frequency) in frequencies.iter().enumerate() {
+            let valid = frequency > 0.
+                && frequency.is_finite()
+                && !frequencies[..symbol].contains(&frequency);
+            if !valid {
+                return Err(ProfileError::InvalidFrequency(symbol));
+            }
+        }
+        let valid = attack_time > 0.
+            && release_time > 0.
+            && attack_time + release_time < beep_time
+            && beep_time.is_finite();
+        if !valid {
+            return Err(ProfileError::InvalidTiming);
+        }
+        Ok(Self {
+            frequencies,
+            beep_time,
+            attack_time,
+            release_time,
+        })
+    }
+
+    // Narrowband profile for telephony and VoIP with a sample rate of 8 kHz.
+    // The frequencies are spaced linearly between 500 Hz and 3135 Hz to stay inside of the
+    // telephone band (300 Hz - 3400 Hz). Speech codecs like GSM and Opus work with frames of
+    // 20 ms and smear short tones, the beeps are longer to span multiple frames.
+    pub fn telephone() -> Self {
+        let mut frequencies = [0.; SYMBOL_COUNT];
+        for (i, frequency) in frequencies.iter_mut().enumerate() {
+            *frequency = TELEPHONE_BASE_FREQ + TELEPHONE_FREQ_STEP * (i as f32);
+        }
+        Self {
+            frequencies,
+            beep_time: TELEPHONE_BEEP_TIME,
+            attack_time: TELEPHONE_ATTACK_TIME,
+            release_time: TELEPHONE_RELEASE_TIME,
+        }
+    }
+
+    pub fn frequencies(&self) -> &[f32; SYMBOL_COUNT] {
+        &self.frequencies
+    }
+
+    pub fn beep_time(&self) -> f32 {
+        self.beep_time
+    }
+
+    pub fn attack_time(&self) -> f32 {
+        self.attack_time
+    }
+
+    pub fn release_time(&self) -> f32 {
+        self.release_time
+    }
+
+    // Minimum sample rate that can represent all frequencies
+    pub fn min_sample_rate(&self) -> u32 {
+        let max_frequency = self.frequencies.iter().cloned().fold(0., f32::max);
+        (max_frequency * 2.0).round() as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test that the built-in profiles are valid and invalid parameters are rejected
+    #[test]
+    fn test_validation() {
+        for profile in [Profile::default(), Profile::telephone()].iter() {
+            let validated = Profile::new(
+                *profile.frequencies(),
+                profile.beep_time(),
+                profile.attack_time(),
+                profile.release_time(),
+            );
+            assert_eq!(validated.as_ref(), Ok(profile));
+        }
+        let frequencies = *Profile::default().frequencies();
+        let mut duplicate = frequencies;
+        duplicate[3] = duplicate[2];
+        let mut negative = frequencies;
+        negative[0] = -1.;
+        let mut nan = frequencies;
+        nan[5] = f32::NAN;
+        let cases = [
+            (
+                duplicate,
+                0.1,
+                0.01,
+                0.01,
+                ProfileError::InvalidFrequency(3),
+            ),
+            (negative, 0.1, 0.01, 0.01, ProfileError::InvalidFrequency(0)),
+            (nan, 0.1, 0.01, 0.01, ProfileError::InvalidFrequency(5)),
+            // Without attack, the envelope divides by zero
+            (frequencies, 0.1, 0., 0.01, ProfileError::InvalidTiming),
+            (frequencies, 0.1, 0.01, -0.01, ProfileError::InvalidTiming),
+            // Attack and release overlap
+            (frequencies, 0.1, 0.05, 0.05, ProfileError::InvalidTiming),
+            (
+                frequencies,
+                f32::INFINITY,
+                0.01,
+                0.01,
+                ProfileError::InvalidTiming,
+            ),
+        ];
+        for &(frequencies, beep_time, attack_time, release_time, ref error) in cases.iter() {
+            assert_eq!(
+                Profile::new(frequencies, beep_time, attack_time, release_time).as_ref(),
+                Err(error)
+            );
+        }
+    }
+}
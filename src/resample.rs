@@ -0,0 +1,147 @@
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+
+// Number of zero crossings of the sinc function on each side of the kernel
+const ZERO_CROSSINGS: usize = 8;
+// Resolution of the precalculated kernel in values per input sample
+const KERNEL_RESOLUTION: usize = 128;
+// Cutoff frequency of the low-pass filter relative to the lower Nyquist frequency
+const CUTOFF: f32 = 0.95;
+
+// Streaming sample rate converter using windowed sinc interpolation
+pub struct Resampler {
+    input_rate: u32,
+    output_rate: u32,
+    // Half width of the kernel in input samples
+    kernel_radius: f32,
+    kernel: Vec<f32>,
+    buffer: VecDeque<f32>,
+    // Index of the first input sample in `buffer`
+    buffer_start: u64,
+    // Number of output samples
+    output_count: u64,
+}
+
+impl Resampler {
+    pub fn new(input_rate: u32, output_rate: u32) -> Self {
+        assert!(
+            input_rate > 0 && output_rate > 0,
+            "sample rate must not be 0"
+        );
+        // Cutoff frequency in cycles per input sample
+        let cutoff = 0.5 * CUTOFF * (output_rate as f32 / input_rate as f32).min(1.);
+        let kernel_radius = (ZERO_CROSSINGS as f32) / (2. * cutoff);
+        let kernel_len = (kernel_radius * (KERNEL_RESOLUTION as f32)).ceil() as usize + 2;
+        let kernel = (0..kernel_len)
+            .map(|i| {
+                let x = (i as f32) / (KERNEL_RESOLUTION as f32);
+                if x >= kernel_radius {
+                    return 0.;
+                }
+                let sinc = if i == 0 {
+                    1.
+                } else {
+                    (2. * PI * cutoff * x).sin() / (2. * PI * cutoff * x)
+                };
+                // Blackman window
+                let window = 0.42
+                    + 0.5 * (PI * x / kernel_radius).cos()
+                    + 0.08 * (2. * PI * x / kernel_radius).cos();
+                2. * cutoff * sinc * window
+            })
+            .collect();
+        Self {
+            input_rate,
+            output_rate,
+            kernel_radius,
+            kernel,
+            buffer: VecDeque::new(),
+            buffer_start: 0,
+            output_count: 0,
+        }
+    }
+
+    pub fn input_rate(&self) -> u32 {
+        self.input_rate
+    }
+
+    pub fn output_rate(&self) -> u32 {
+        self.output_rate
+    }
+
+    #[inline]
+    fn kernel_at(&self, x: f32) -> f32 {
+        let pos = x.abs() * (KERNEL_RESOLUTION as f32);
+        let i = pos as usize;
+        if i + 1 >= self.kernel.len() {
+            return 0.;
+        }
+        let frac = pos - (i as f32);
+        self.kernel[i] * (1. - frac) + self.kernel[i + 1] * frac
+    }
+
+    // Commit an input sample
+    pub fn push(&mut self, sample: f32) {
+        self.buffer.push_back(sample);
+    }
+
+    // Get the next output sample, if enough input samples are available
+    pub fn pop(&mut self) -> Option<f32> {
+        // Position of the output sample in input samples
+        let product = self.output_count * (self.input_rate as u64);
+        let pos_int = product / (self.output_rate as u64);
+        let pos_frac = ((product % (self.output_rate as u64)) as f32) / (self.output_rate as f32);
+        let radius = self.kernel_radius.ceil() as u64;
+        let first = pos_int.saturating_sub(radius);
+        let last = pos_int + radius;
+        if self.buffer_start + (self.buffer.len() as u64) <= last {
+            return None;
+        }
+        // Drop input samples that are no longer needed
+        while self.buffer_start < first {
+            self.buffer.pop_front();
+            self.buffer_start += 1;
+        }
+        let mut output = 0.;
+        for i in self.buffer_start..=last {
+            let x = ((i as i64 - pos_int as i64) as f32) - pos_frac;
+            output += self.buffer[(i - self.buffer_start) as usize] * self.kernel_at(x);
+        }
+        self.output_count += 1;
+        Some(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(frequency: f32, sample_rate: u32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| (2. * PI * frequency * (i as f32) / (sample_rate as f32)).sin())
+            .collect()
+    }
+
+    // Test conversion of a sine wave against the ideal result
+    #[test]
+    fn test_resample_sine() {
+        const FREQUENCY: f32 = 1000.;
+        for &(input_rate, output_rate) in [(44100, 48000), (96000, 22050), (8000, 44100)].iter() {
+            let mut resampler = Resampler::new(input_rate, output_rate);
+            let mut output = Vec::new();
+            for &sample in sine(FREQUENCY, input_rate, input_rate as usize).iter() {
+                resampler.push(sample);
+                while let Some(sample) = resampler.pop() {
+                    output.push(sample);
+                }
+            }
+            let expected = sine(FREQUENCY, output_rate, output.len());
+            // Skip the start, because the input is not band-limited there
+            let skip = (output_rate / 100) as usize;
+            for (i, (a, b)) in output.iter().zip(expected.iter()).enumerate().skip(skip) {
+                assert!((a - b).abs() < 0.01, "sample {}: {} != {}", i, a, b);
+            }
+            assert!(output.len() + (output_rate / 100) as usize >= output_rate as usize);
+        }
+    }
+}
@@ -0,0 +1,428 @@
+use std::f32::consts::PI;
+
+use rand::prelude::*;
+
+use super::super::Resampler;
+
+// Crossover frequency of the frequency response tilt
+const TILT_CROSSOVER_FREQ: f32 = 1000.;
+// Delay of the first reflection of the synthetic room impulse response
+const REVERB_PREDELAY_TIME: f32 = 0.005;
+// Number of reflections per second in the synthetic room impulse response
+const REVERB_REFLECTION_DENSITY: f32 = 2000.;
+// Rate of syllables in the speech-like interference
+const SYLLABLE_RATE: f32 = 4.;
+// Highest harmonic of the speech-like interference
+const SPEECH_MAX_FREQ: f32 = 3500.;
+
+// Deterministic simulation of an acoustic channel between a speaker and a microphone.
+// The effects are applied in this order: frequency response tilt, reverb, clock drift,
+// noise and interference, clipping and dropouts.
+// Levels of noise and interference are relative to the average power of the non-silent
+// samples of the signal.
+pub struct Channel {
+    sample_rate: u32,
+    rng: SmallRng,
+    tilt: Option<f32>,
+    reverb: Option<(f32, f32)>,
+    drift: Option<i32>,
+    white_noise: Option<f32>,
+    pink_noise: Option<f32>,
+    speech: Option<f32>,
+    clipping: Option<f32>,
+    dropouts: Option<(f32, f32)>,
+}
+
+impl Channel {
+    pub fn new(sample_rate: u32, seed: u64) -> Self {
+        Self {
+            sample_rate,
+            rng: SmallRng::seed_from_u64(seed),
+            tilt: None,
+            reverb: None,
+            drift: None,
+            white_noise: None,
+            pink_noise: None,
+            speech: None,
+            clipping: None,
+            dropouts: None,
+        }
+    }
+
+    // Amplify frequencies above 1 kHz by `gain_db` relative to frequencies below
+    // (negative values attenuate)
+    pub fn tilt(mut self, gain_db: f32) -> Self {
+        self.tilt = Some(gain_db);
+        self
+    }
+
+    // Convolve with a synthetic room impulse response with the reverberation time `rt60` in
+    // seconds. `wet` is the energy of the reflections relative to the direct path.
+    pub fn reverb(mut self, rt60: f32, wet: f32) -> Self {
+        self.reverb = Some((rt60, wet));
+        self
+    }
+
+    // Clock drift of the receiver relative to the sender in parts per million
+    pub fn drift(mut self, ppm: i32) -> Self {
+        self.drift = Some(ppm);
+        self
+    }
+
+    // Add white noise with the SNR `snr_db`
+    pub fn white_noise(mut self, snr_db: f32) -> Self {
+        self.white_noise = Some(snr_db);
+        self
+    }
+
+    // Add pink noise with the SNR `snr_db`
+    pub fn pink_noise(mut self, snr_db: f32) -> Self {
+        self.pink_noise = Some(snr_db);
+        self
+    }
+
+    // Mix with speech-like interference with the signal-to-interference ratio `sir_db`
+    pub fn speech(mut self, sir_db: f32) -> Self {
+        self.speech = Some(sir_db);
+        self
+    }
+
+    // Clip samples to `level`
+    pub fn clipping(mut self, level: f32) -> Self {
+        self.clipping = Some(level);
+        self
+    }
+
+    // Mute the audio for `duration` seconds on average `rate` times per second
+    pub fn dropouts(mut self, rate: f32, duration: f32) -> Self {
+        self.dropouts = Some((rate, duration));
+        self
+    }
+
+    // Generate white or pink noise with unit power
+    pub fn generate_noise(&mut self, len: usize, pink: bool) -> Vec<f32> {
+        let mut noise = if pink {
+            self.pink(len)
+        } else {
+            (0..len).map(|_| self.gaussian()).collect()
+        };
+        let power = noise.iter().map(|x| x * x).sum::<f32>() / (len.max(1) as f32);
+        if power > 0. {
+            for sample in noise.iter_mut() {
+                *sample /= power.sqrt();
+            }
+        }
+        noise
+    }
+
+    // Pass `input` through the channel.
+    // Consecutive calls are independent, but use the same random number generator.
+    pub fn process(&mut self, input: &[f32]) -> Vec<f32> {
+        let mut samples = input.to_vec();
+        if let Some(gain_db) = self.tilt {
+            samples = self.apply_tilt(&samples, gain_db);
+        }
+        if let Some((rt60, wet)) = self.reverb {
+            samples = self.apply_reverb(&samples, rt60, wet);
+        }
+        if let Some(ppm) = self.drift {
+            samples = apply_drift(&samples, ppm);
+        }
+        let power = signal_power(&samples);
+        if let Some(snr_db) = self.white_noise {
+            let noise: Vec<f32> = (0..samples.len()).map(|_| self.gaussian()).collect();
+            mix(&mut samples, &noise, power, snr_db);
+        }
+        if let Some(snr_db) = self.pink_noise {
+            let noise = self.pink(samples.len());
+            mix(&mut samples, &noise, power, snr_db);
+        }
+        if let Some(sir_db) = self.speech {
+            let interference = self.speech_like(samples.len());
+            mix(&mut samples, &interference, power, sir_db);
+        }
+        if let Some(level) = self.clipping {
+            for sample in samples.iter_mut() {
+                *sample = sample.max(-level).min(level);
+            }
+        }
+        if let Some((rate, duration)) = self.dropouts {
+            let duration_len = ((self.sample_rate as f32) * duration).round() as usize;
+            let probability = rate / (self.sample_rate as f32);
+            let mut i = 0;
+            while i < samples.len() {
+                if self.rng.gen::<f32>() < probability {
+                    let end = (i + duration_len).min(samples.len());
+                    for sample in samples[i..end].iter_mut() {
+                        *sample = 0.;
+                    }
+                    i = end;
+                } else {
+                    i += 1;
+                }
+            }
+        }
+        samples
+    }
+
+    fn apply_tilt(&self, samples: &[f32], gain_db: f32) -> Vec<f32> {
+        // Split into low and high frequencies with two cascaded one-pole low-pass filters
+        let gain = 10f32.powf(gain_db / 20.);
+        let alpha = 1. - (-2. * PI * TILT_CROSSOVER_FREQ / (self.sample_rate as f32)).exp();
+        let (mut low1, mut low2) = (0., 0.);
+        samples
+            .iter()
+            .map(|&sample| {
+                low1 += alpha * (sample - low1);
+                low2 += alpha * (low1 - low2);
+                low2 + (sample - low2) * gain
+            })
+            .collect()
+    }
+
+    fn apply_reverb(&mut self, samples: &[f32], rt60: f32, wet: f32) -> Vec<f32> {
+        // Sparse impulse response with exponentially decaying random reflections
+        let sample_rate = self.sample_rate as f32;
+        let first = (sample_rate * REVERB_PREDELAY_TIME).round() as usize;
+        let len = (sample_rate * rt60).round() as usize;
+        let reflections_len = (REVERB_REFLECTION_DENSITY * rt60).round() as usize;
+        let mut impulse_response = vec![0.; first.max(len) + 1];
+        for _ in 0..reflections_len {
+            let delay = self.rng.gen_range(first, impulse_response.len());
+            // Amplitude decays by 60 dB after `rt60`
+            let decay = 10f32.powf(-3. * (delay as f32) / (sample_rate * rt60));
+            let sign = if self.rng.gen::<bool>() { 1. } else { -1. };
+            impulse_response[delay] += sign * decay;
+        }
+        let energy: f32 = impulse_response.iter().map(|a| a * a).sum();
+        let scale = if energy > 0. {
+            (wet / energy).sqrt()
+        } else {
+            0.
+        };
+        let reflections: Vec<(usize, f32)> = impulse_response
+            .iter()
+            .enumerate()
+            .filter(|(_, &a)| a != 0.)
+            .map(|(delay, &a)| (delay, a * scale))
+            .collect();
+        let mut output = samples.to_vec();
+        output.extend(vec![0.; impulse_response.len()]);
+        for (i, &sample) in samples.iter().enumerate() {
+            if sample == 0. {
+                continue;
+            }
+            for &(delay, amplitude) in reflections.iter() {
+                output[i + delay] += sample * amplitude;
+            }
+        }
+        output
+    }
+
+    // Sample of the standard normal distribution (Box-Muller transform)
+    fn gaussian(&mut self) -> f32 {
+        let u1 = 1. - self.rng.gen::<f32>();
+        let u2 = self.rng.gen::<f32>();
+        (-2. * u1.ln()).sqrt() * (2. * PI * u2).cos()
+    }
+
+    // Pink noise filtered from white noise (Paul Kellet's economy method)
+    fn pink(&mut self, len: usize) -> Vec<f32> {
+        let (mut b0, mut b1, mut b2) = (0., 0., 0.);
+        (0..len)
+            .map(|_| {
+                let white = self.gaussian();
+                b0 = 0.99765 * b0 + white * 0.099_046;
+                b1 = 0.963 * b1 + white * 0.296_516_4;
+                b2 = 0.57 * b2 + white * 1.052_691_3;
+                b0 + b1 + b2 + white * 0.1848
+            })
+            .collect()
+    }
+
+    // Voiced syllables with a varying pitch, separated by pauses
+    fn speech_like(&mut self, len: usize) -> Vec<f32> {
+        let sample_rate = self.sample_rate as f32;
+        let mut output = vec![0.; len];
+        let mut i = 0;
+        while i < len {
+            let syllable_len =
+                (sample_rate / SYLLABLE_RATE * self.rng.gen_range(0.5, 1.)).round() as usize;
+            let pause_len =
+                (sample_rate / SYLLABLE_RATE * self.rng.gen_range(0., 0.5)).round() as usize;
+            let pitch = self.rng.gen_range(100., 250.);
+            let pitch_slope = self.rng.gen_range(-0.5, 0.5);
+            let harmonics_len = (SPEECH_MAX_FREQ / pitch) as usize;
+            // Random spectral envelope, that emphasizes some harmonics like formants
+            let amplitudes: Vec<f32> = (1..=harmonics_len)
+                .map(|k| self.rng.gen_range(0.2, 1.) / (k as f32))
+                .collect();
+            let mut phase = 0.;
+            for j in 0..syllable_len.min(len - i) {
+                let t = (j as f32) / (syllable_len as f32);
+                phase += 2. * PI * pitch * (1. + pitch_slope * t) / sample_rate;
+                let envelope = (PI * t).sin();
+                let sample: f32 = amplitudes
+                    .iter()
+                    .enumerate()
+                    .map(|(k, a)| a * (phase * ((k + 1) as f32)).sin())
+                    .sum();
+                output[i + j] = sample * envelope;
+            }
+            i += syllable_len + pause_len;
+        }
+        output
+    }
+}
+
+fn apply_drift(samples: &[f32], ppm: i32) -> Vec<f32> {
+    const RATE: i64 = 1_000_000;
+    let mut resampler = Resampler::new(RATE as u32, (RATE + ppm as i64) as u32);
+    let mut output = Vec::new();
+    // Flush the resampler with silence
+    for &sample in samples.iter().chain([0.; 64].iter()) {
+        resampler.push(sample);
+        while let Some(sample) = resampler.pop() {
+            output.push(sample);
+        }
+    }
+    output.truncate(((samples.len() as i64) * (RATE + ppm as i64) / RATE) as usize);
+    output
+}
+
+// Average power of the non-silent samples
+fn signal_power(samples: &[f32]) -> f32 {
+    let (sum, len) = samples
+        .iter()
+        .filter(|&&sample| sample != 0.)
+        .fold((0., 0), |(sum, len), &sample| {
+            (sum + sample * sample, len + 1)
+        });
+    if len == 0 {
+        0.
+    } else {
+        sum / (len as f32)
+    }
+}
+
+// Add `noise` scaled to the ratio `ratio_db` relative to `power`
+fn mix(samples: &mut [f32], noise: &[f32], power: f32, ratio_db: f32) {
+    let noise_power = noise.iter().map(|x| x * x).sum::<f32>() / (noise.len().max(1) as f32);
+    if noise_power == 0. {
+        return;
+    }
+    let gain = (power / noise_power / 10f32.powf(ratio_db / 10.)).sqrt();
+    for (sample, noise) in samples.iter_mut().zip(noise.iter()) {
+        *sample += noise * gain;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 44100;
+
+    fn sine(frequency: f32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| (2. * PI * frequency * (i as f32) / (SAMPLE_RATE as f32)).sin() * 0.5)
+            .collect()
+    }
+
+    fn power(samples: &[f32]) -> f32 {
+        samples.iter().map(|x| x * x).sum::<f32>() / (samples.len() as f32)
+    }
+
+    fn snr_db(signal: &[f32], output: &[f32]) -> f32 {
+        let noise: Vec<f32> = output.iter().zip(signal).map(|(a, b)| a - b).collect();
+        10. * (power(signal) / power(&noise)).log10()
+    }
+
+    // Test that the output only depends on the seed
+    #[test]
+    fn test_deterministic() {
+        let input = sine(1000., SAMPLE_RATE as usize);
+        let new_channel = |seed| {
+            Channel::new(SAMPLE_RATE, seed)
+                .reverb(0.3, 0.5)
+                .pink_noise(10.)
+                .speech(10.)
+                .dropouts(2., 0.01)
+        };
+        let output = new_channel(1).process(&input);
+        assert_eq!(output, new_channel(1).process(&input));
+        assert_ne!(output, new_channel(2).process(&input));
+    }
+
+    // Test the levels of noise and interference
+    #[test]
+    fn test_snr() {
+        let input = sine(1000., SAMPLE_RATE as usize);
+        for &snr in [-5., 0., 10., 20.].iter() {
+            for channel in [
+                Channel::new(SAMPLE_RATE, 0).white_noise(snr),
+                Channel::new(SAMPLE_RATE, 0).pink_noise(snr),
+                Channel::new(SAMPLE_RATE, 0).speech(snr),
+            ]
+            .iter_mut()
+            {
+                let output = channel.process(&input);
+                assert!((snr_db(&input, &output) - snr).abs() < 0.5);
+            }
+        }
+    }
+
+    // Test the tilt of the frequency response
+    #[test]
+    fn test_tilt() {
+        let mut channel = Channel::new(SAMPLE_RATE, 0).tilt(-12.);
+        let low = power(&channel.process(&sine(50., SAMPLE_RATE as usize)));
+        let high = power(&channel.process(&sine(15000., SAMPLE_RATE as usize)));
+        let tilt_db = 10. * (high / low).log10();
+        assert!((tilt_db + 12.).abs() < 1., "{}", tilt_db);
+    }
+
+    // Test that the clock drift stretches the signal
+    #[test]
+    fn test_drift() {
+        const PPM: i32 = 1000;
+        let input = sine(1000., SAMPLE_RATE as usize);
+        let output = Channel::new(SAMPLE_RATE, 0).drift(PPM).process(&input);
+        assert_eq!(
+            output.len(),
+            input.len() + input.len() * (PPM as usize) / 1_000_000
+        );
+        // The frequency is reduced by the drift
+        let expected = sine(1000. / (1. + (PPM as f32) * 1e-6), output.len());
+        assert!(snr_db(&expected[1000..], &output[1000..]) > 30.);
+    }
+
+    // Test the reverb tail, clipping and dropouts
+    #[test]
+    fn test_reverb_clipping_dropouts() {
+        let mut impulse = vec![0.; SAMPLE_RATE as usize];
+        impulse[0] = 1.;
+        let output = Channel::new(SAMPLE_RATE, 0)
+            .reverb(0.5, 1.)
+            .process(&impulse);
+        assert_eq!(output[0], 1.);
+        let tail_energy: f32 = output[1..].iter().map(|x| x * x).sum();
+        assert!((tail_energy - 1.).abs() < 1e-4);
+        let late_energy: f32 = output[SAMPLE_RATE as usize / 2..]
+            .iter()
+            .map(|x| x * x)
+            .sum();
+        assert!(late_energy < 1e-5);
+
+        let input = sine(1000., SAMPLE_RATE as usize);
+        let output = Channel::new(SAMPLE_RATE, 0).clipping(0.25).process(&input);
+        assert!(output.iter().all(|x| x.abs() <= 0.25));
+
+        let output = Channel::new(SAMPLE_RATE, 0)
+            .dropouts(10., 0.01)
+            .process(&input);
+        let muted = output.iter().filter(|&&x| x == 0.).count() as f32;
+        let expected = (SAMPLE_RATE as f32) * 10. * 0.01;
+        assert!((muted - expected).abs() < expected * 0.5, "{}", muted);
+    }
+}
@@ -0,0 +1,508 @@
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+
+use super::{
+    Envelope, Profile, ECC_LEN, GF, PACKET_LEN, PAYLOAD_LEN, START_SYMBOLS, START_SYMBOLS_LEN,
+    SYMBOL_COUNT,
+};
+
+// Default time after the end of a transmission, during which received audio is still
+// treated as our own transmission (see method `Transceiver::set_transmit_tail`)
+pub const TRANSMIT_TAIL_TIME: f32 = 0.25;
+// Generated samples that are kept for the echo canceller in addition to its filter length.
+// Older samples are dropped, when the output runs ahead of the input.
+const RECORDING_SLACK_TIME: f32 = 0.1;
+// Maximum number of events that are kept until they are polled, older events are dropped
+pub const MAX_TRANSMIT_EVENTS: usize = 64;
+
+// Identifies a packet or an interleaved frame in the transmit queue
+pub type PacketId = u64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransmitEvent {
+    // `sample` is the index of the first sample of the packet in the stream
+    Started { id: PacketId, sample: u64 },
+    // `sample` is the index of the first sample after the packet in the stream
+    Finished { id: PacketId, sample: u64 },
+}
+
+// Handling of our own transmissions from the built-in transmitter by the receiver
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DuplexMode {
+    // Receive everything, including our own transmissions
+    Full,
+    // Ignore the input while transmitting
+    Mute,
+    // Drop received payloads that are identical to the payloads we are transmitting
+    DropOwn,
+}
+
+impl Default for DuplexMode {
+    fn default() -> Self {
+        DuplexMode::Full
+    }
+}
+
+struct QueuedFrame {
+    id: PacketId,
+    payloads: Vec<[u8; PAYLOAD_LEN]>,
+    frequencies: Vec<f32>,
+}
+
+struct PlayingFrame {
+    id: PacketId,
+    frequencies: Vec<f32>,
+    beep: usize,
+    beep_pos: usize,
+    gain: f32,
+}
+
+// Generates the audio data for queued packets on demand.
+// Created by the transceiver (see method `Transceiver::transmitter`), that keeps the settings
+// shared with the receiver, like the interleave depth, in sync.
+pub struct TransmitStream {
+    sample_rate: u32,
+    profile: Profile,
+    envelope: Envelope,
+    tone_gains: [f32; SYMBOL_COUNT],
+    interleave_depth: usize,
+    rs_encoder: reed_solomon::Encoder<GF>,
+    beep_len: usize,
+    gap_len: usize,
+    remaining_gap_len: usize,
+    queue: VecDeque<QueuedFrame>,
+    playing_frame: Option<PlayingFrame>,
+    next_id: PacketId,
+    sample_count: u64,
+    events: VecDeque<TransmitEvent>,
+    tail_len: u64,
+    // Sample count at the end of the last transmission
+    finished_at: Option<u64>,
+    // Payloads of playing frames and of frames that finished within the tail.
+    // The sample count at the end is set when the frame finished.
+    own_payloads: VecDeque<(Option<u64>, Vec<[u8; PAYLOAD_LEN]>)>,
+    // Generated samples that were not yet consumed by the echo canceller
+    recorded_samples: Option<VecDeque<f32>>,
+    max_recorded_samples: usize,
+}
+
+impl TransmitStream {
+    pub(crate) fn new(sample_rate: u32, profile: Profile) -> Self {
+        let beep_len = ((profile.beep_time() * (sample_rate as f32)).round() as usize).max(1);
+        Self {
+            sample_rate,
+            profile,
+            envelope: Default::default(),
+            tone_gains: [1.; SYMBOL_COUNT],
+            interleave_depth: 1,
+            rs_encoder: reed_solomon::Encoder::new(ECC_LEN),
+            beep_len,
+            gap_len: 0,
+            remaining_gap_len: 0,
+            queue: VecDeque::new(),
+            playing_frame: None,
+            next_id: 0,
+            sample_count: 0,
+            events: VecDeque::new(),
+            tail_len: ((sample_rate as f32) * TRANSMIT_TAIL_TIME).round() as u64,
+            finished_at: None,
+            own_payloads: VecDeque::new(),
+            recorded_samples: None,
+            max_recorded_samples: 0,
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    // See method `Transceiver::set_envelope`
+    pub(crate) fn set_envelope(&mut self, envelope: Envelope) {
+        envelope.validate();
+        self.envelope = envelope;
+    }
+
+    // See method `Transceiver::set_tone_gains`
+    pub fn set_tone_gains(&mut self, tone_gains: [f32; SYMBOL_COUNT]) {
+        self.tone_gains = tone_gains;
+    }
+
+    // See method `Transceiver::set_interleave_depth`
+    pub(crate) fn set_interleave_depth(&mut self, depth: usize) {
+        assert!(depth > 0, "interleave depth must be atleast 1");
+        self.interleave_depth = depth;
+    }
+
+    // Insert `gap` seconds of silence between consecutive packets (default: `0.`)
+    pub fn set_gap(&mut self, gap: f32) {
+        assert!(gap >= 0., "gap must not be negative");
+        self.gap_len = ((self.sample_rate as f32) * gap).round() as usize;
+    }
+
+    // Queue the packet containing `payload` for transmission
+    pub fn enqueue(&mut self, payload: &[u8; PAYLOAD_LEN]) -> PacketId {
+        assert_eq!(
+            self.interleave_depth, 1,
+            "interleaving is enabled: use method `enqueue_interleaved`"
+        );
+        self.enqueue_interleaved(&[*payload])
+    }
+
+    // Queue the frame containing the interleaved packets with `payloads` for transmission.
+    // The number of payloads must be equal to the interleave depth.
+    pub fn enqueue_interleaved(&mut self, payloads: &[[u8; PAYLOAD_LEN]]) -> PacketId {
+        let id = self.next_id;
+        self.next_id += 1;
+        let frequencies = self.frequencies(payloads);
+        self.queue.push_back(QueuedFrame {
+            id,
+            payloads: payloads.to_vec(),
+            frequencies,
+        });
+        id
+    }
+
+    // Returns `true` if no packet is playing or queued
+    pub fn is_idle(&self) -> bool {
+        self.playing_frame.is_none() && self.queue.is_empty()
+    }
+
+    // See method `Transceiver::set_transmit_tail`
+    pub(crate) fn set_tail(&mut self, tail: f32) {
+        assert!(tail >= 0., "tail must not be negative");
+        self.tail_len = ((self.sample_rate as f32) * tail).round() as u64;
+        self.remove_old_payloads();
+    }
+
+    // Returns `true` while a packet is playing and during the tail after it
+    pub(crate) fn is_active(&self) -> bool {
+        self.playing_frame.is_some()
+            || self.finished_at.map_or(false, |finished_at| {
+                self.sample_count - finished_at < self.tail_len
+            })
+    }
+
+    // Returns `true` if `payload` is playing or finished playing within the tail
+    pub(crate) fn is_own_payload(&mut self, payload: &[u8; PAYLOAD_LEN]) -> bool {
+        self.remove_old_payloads();
+        self.own_payloads
+            .iter()
+            .any(|(_, payloads)| payloads.contains(payload))
+    }
+
+    fn remove_old_payloads(&mut self) {
+        let (sample_count, tail_len) = (self.sample_count, self.tail_len);
+        self.own_payloads.retain(|(finished_at, _)| {
+            finished_at.map_or(true, |finished_at| sample_count - finished_at < tail_len)
+        });
+    }
+
+    // Keep generated samples for an echo canceller with a filter of `len` samples.
+    // Set to `0` to disable.
+    pub(crate) fn set_recording(&mut self, len: usize) {
+        let slack_len = ((self.sample_rate as f32) * RECORDING_SLACK_TIME).round() as usize;
+        self.max_recorded_samples = len + slack_len;
+        self.recorded_samples = if len > 0 { Some(VecDeque::new()) } else { None };
+    }
+
+    pub(crate) fn pop_recorded_sample(&mut self) -> Option<f32> {
+        self.recorded_samples.as_mut().and_then(VecDeque::pop_front)
+    }
+
+    // Get the next event about started or finished packets. Only the last
+    // `MAX_TRANSMIT_EVENTS` events are kept, callers should poll after each call to `fill`.
+    pub fn poll_event(&mut self) -> Option<TransmitEvent> {
+        self.events.pop_front()
+    }
+
+    fn push_event(&mut self, event: TransmitEvent) {
+        if self.events.len() >= MAX_TRANSMIT_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    // Fill `buffer` with the next samples. Silence is generated while no packet is queued.
+    pub fn fill(&mut self, buffer: &mut [f32]) {
+        for sample in buffer.iter_mut() {
+            *sample = self.next_sample();
+        }
+    }
+
+    fn next_sample(&mut self) -> f32 {
+        let sample = self.generate_sample();
+        if let Some(recorded_samples) = self.recorded_samples.as_mut() {
+            if recorded_samples.len() >= self.max_recorded_samples {
+                recorded_samples.pop_front();
+            }
+            recorded_samples.push_back(sample);
+        }
+        sample
+    }
+
+    fn generate_sample(&mut self) -> f32 {
+        if self.playing_frame.is_none() {
+            if self.remaining_gap_len > 0 {
+                self.remaining_gap_len -= 1;
+                self.sample_count += 1;
+                return 0.;
+            }
+            let frame = match self.queue.pop_front() {
+                Some(frame) => frame,
+                None => {
+                    self.sample_count += 1;
+                    return 0.;
+                }
+            };
+            self.push_event(TransmitEvent::Started {
+                id: frame.id,
+                sample: self.sample_count,
+            });
+            self.remove_old_payloads();
+            self.own_payloads.push_back((None, frame.payloads));
+            self.playing_frame = Some(PlayingFrame {
+                id: frame.id,
+                gain: self.tone_gain(frame.frequencies[0]),
+                frequencies: frame.frequencies,
+                beep: 0,
+                beep_pos: 0,
+            });
+        }
+        let frame = self.playing_frame.as_mut().unwrap();
+        let sample = beep_sample(
+            &self.profile,
+            self.envelope,
+            frame.frequencies[frame.beep],
+            frame.gain,
+            frame.beep_pos,
+            self.beep_len,
+        );
+        self.sample_count += 1;
+        frame.beep_pos += 1;
+        if frame.beep_pos == self.beep_len {
+            frame.beep_pos = 0;
+            frame.beep += 1;
+            if frame.beep == frame.frequencies.len() {
+                let id = frame.id;
+                self.playing_frame = None;
+                self.push_event(TransmitEvent::Finished {
+                    id,
+                    sample: self.sample_count,
+                });
+                self.remaining_gap_len = self.gap_len;
+                self.finished_at = Some(self.sample_count);
+                if let Some(own_payloads) = self.own_payloads.back_mut() {
+                    own_payloads.0 = Some(self.sample_count);
+                }
+            } else {
+                let frequency = frame.frequencies[frame.beep];
+                self.playing_frame.as_mut().unwrap().gain = self.tone_gain(frequency);
+            }
+        }
+        sample
+    }
+
+    // Get the beep frequencies for the frame containing the interleaved packets with `payloads`
+    pub(crate) fn frequencies(&self, payloads: &[[u8; PAYLOAD_LEN]]) -> Vec<f32> {
+        if payloads.len() != self.interleave_depth {
+            panic!(
+                "wrong number of payloads: must be {} but is {}",
+                self.interleave_depth,
+                payloads.len()
+            );
+        }
+        let codewords: Vec<[u8; PACKET_LEN]> = payloads
+            .iter()
+            .map(|payload| self.encode(payload))
+            .collect();
+        let mut frequencies = Vec::with_capacity(PACKET_LEN * self.interleave_depth);
+        for i in 0..PACKET_LEN {
+            for codeword in codewords.iter() {
+                frequencies.push(self.profile.frequencies()[codeword[i] as usize]);
+            }
+        }
+        frequencies
+    }
+
+    fn encode(&self, payload: &[u8; PAYLOAD_LEN]) -> [u8; PACKET_LEN] {
+        if let Some(v) = payload.iter().find(|v| (**v as usize) >= SYMBOL_COUNT) {
+            panic!(
+                "symbol out of bounds: must be smaller than {} but is {}",
+                SYMBOL_COUNT, v
+            );
+        }
+        let mut data: [u8; START_SYMBOLS_LEN + PAYLOAD_LEN] = Default::default();
+        data[..START_SYMBOLS_LEN].copy_from_slice(&START_SYMBOLS);
+        data[START_SYMBOLS_LEN..].copy_from_slice(payload);
+        let encoded_data = self.rs_encoder.encode(&data);
+        let mut codeword: [u8; PACKET_LEN] = Default::default();
+        codeword.copy_from_slice(&encoded_data);
+        codeword
+    }
+
+    // Get the gain for the symbol with the frequency closest to `frequency`
+    fn tone_gain(&self, frequency: f32) -> f32 {
+        let mut tone_gain = 1.;
+        let mut min_distance = f32::INFINITY;
+        for (i, &symbol_frequency) in self.profile.frequencies().iter().enumerate() {
+            let distance = (symbol_frequency - frequency).abs();
+            if distance < min_distance {
+                min_distance = distance;
+                tone_gain = self.tone_gains[i];
+            }
+        }
+        tone_gain
+    }
+
+    // See method `Transceiver::generate_beep_with_sample_rate`
+    pub(crate) fn generate_beep(&self, frequency: f32, sample_rate: u32) -> Vec<f32> {
+        let gain = self.tone_gain(frequency);
+        let samples_len = (self.profile.beep_time() * (sample_rate as f32)).round() as usize;
+        (0..samples_len)
+            .map(|i| {
+                beep_sample(
+                    &self.profile,
+                    self.envelope,
+                    frequency,
+                    gain,
+                    i,
+                    samples_len,
+                )
+            })
+            .collect()
+    }
+}
+
+// Never ends, silence is generated while no packet is queued
+impl Iterator for TransmitStream {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        Some(self.next_sample())
+    }
+}
+
+#[inline]
+fn beep_sample(
+    profile: &Profile,
+    envelope: Envelope,
+    frequency: f32,
+    gain: f32,
+    i: usize,
+    samples_len: usize,
+) -> f32 {
+    let t = profile.beep_time() / (samples_len as f32) * (i as f32);
+    let window = envelope.amplitude(t, profile) * gain;
+    (t * frequency * 2. * PI).sin() * window
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::*;
+    use super::super::{Transceiver, BEEP_TIME};
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 44100;
+
+    // Test that the stream generates the same audio data as `generate_beep`
+    #[test]
+    fn test_stream_matches_beeps() {
+        let mut transceiver = Transceiver::new(SAMPLE_RATE);
+        transceiver.set_envelope(Envelope::Tukey { taper: 0.5 });
+        let mut expected = Vec::new();
+        for &frequency in transceiver.send(&rand_payload(0)).iter() {
+            expected.extend(transceiver.generate_beep(frequency));
+        }
+        transceiver.transmitter().enqueue(&rand_payload(0));
+        let samples: Vec<f32> = transceiver.transmitter().take(expected.len()).collect();
+        assert_eq!(samples, expected);
+        assert!(transceiver.transmitter().is_idle());
+    }
+
+    // Test events and reception of queued packets with gaps
+    #[test]
+    fn test_stream_events() {
+        const SEND_COUNT: usize = 3;
+        const GAP: f32 = 0.5;
+        let mut transceiver = Transceiver::new(SAMPLE_RATE);
+        transceiver.transmitter().set_gap(GAP);
+        let ids: Vec<PacketId> = (0..SEND_COUNT)
+            .map(|i| transceiver.transmitter().enqueue(&rand_payload(i)))
+            .collect();
+        let packet_len = (PACKET_LEN as u64) * ((SAMPLE_RATE as f32) * BEEP_TIME).round() as u64;
+        let gap_len = ((SAMPLE_RATE as f32) * GAP).round() as u64;
+        let total_len = (packet_len + gap_len) * (SEND_COUNT as u64);
+        let mut buffer = [0.; 1000];
+        let mut received = Vec::new();
+        let mut events = Vec::new();
+        for _ in 0..(total_len as usize / buffer.len() + 1) {
+            transceiver.transmitter().fill(&mut buffer);
+            while let Some(event) = transceiver.transmitter().poll_event() {
+                events.push(event);
+            }
+            for &sample in buffer.iter() {
+                received.extend(transceiver.push_sample(sample));
+            }
+        }
+        let mut expected_events = Vec::new();
+        for (i, &id) in ids.iter().enumerate() {
+            let start = (packet_len + gap_len) * (i as u64);
+            expected_events.push(TransmitEvent::Started { id, sample: start });
+            expected_events.push(TransmitEvent::Finished {
+                id,
+                sample: start + packet_len,
+            });
+        }
+        assert_eq!(events, expected_events);
+        let expected: Vec<[u8; PAYLOAD_LEN]> = (0..SEND_COUNT).map(rand_payload).collect();
+        assert_eq!(received, expected);
+    }
+
+    // Test that the samples for the echo canceller are bounded, when only output is pulled
+    #[test]
+    fn test_recording_bounded() {
+        const LEN: usize = 1000;
+        let mut transceiver = Transceiver::new(SAMPLE_RATE);
+        transceiver.set_echo_cancellation(LEN as f32 / SAMPLE_RATE as f32);
+        transceiver.transmitter().enqueue(&rand_payload(0));
+        let mut buffer = [0.; 1000];
+        for _ in 0..100 {
+            transceiver.transmitter().fill(&mut buffer);
+        }
+        let transmitter = transceiver.transmitter();
+        let max_len = LEN + ((SAMPLE_RATE as f32) * RECORDING_SLACK_TIME).round() as usize;
+        assert_eq!(
+            transmitter.recorded_samples.as_ref().unwrap().len(),
+            max_len
+        );
+        // The input continues with the newest samples
+        let mut last_samples = vec![0.; max_len];
+        transmitter.fill(&mut last_samples);
+        for &sample in last_samples.iter() {
+            assert_eq!(transmitter.pop_recorded_sample(), Some(sample));
+        }
+        assert_eq!(transmitter.pop_recorded_sample(), None);
+    }
+
+    // Test that old events are dropped, when they are not polled
+    #[test]
+    fn test_events_bounded() {
+        const SAMPLE_RATE: u32 = 8000;
+        const SEND_COUNT: usize = MAX_TRANSMIT_EVENTS / 2 + 8;
+        let mut stream = TransmitStream::new(SAMPLE_RATE, Default::default());
+        let ids: Vec<PacketId> = (0..SEND_COUNT)
+            .map(|i| stream.enqueue(&rand_payload(i)))
+            .collect();
+        let packet_len = PACKET_LEN * ((SAMPLE_RATE as f32) * BEEP_TIME).round() as usize;
+        stream.fill(&mut vec![0.; packet_len * SEND_COUNT]);
+        let events: Vec<TransmitEvent> = std::iter::from_fn(|| stream.poll_event()).collect();
+        assert_eq!(events.len(), MAX_TRANSMIT_EVENTS);
+        assert_eq!(
+            events.last(),
+            Some(&TransmitEvent::Finished {
+                id: ids[SEND_COUNT - 1],
+                sample: (packet_len * SEND_COUNT) as u64,
+            })
+        );
+    }
+}
@@ -222,7 +222,7 @@ impl<T: gf::GF2_X> Decoder<T> {
         let mut X = Polynom::new();
 
         for px in coef_pos.iter() {
-            let l = (255 - px) as i32;
+            let l = (T::get_field_charac() - *px as usize) as i32;
             X.push(T::pow(2, -l))
         }
 
@@ -489,4 +489,24 @@ mod tests {
 
         assert_eq!(result, **decoded);
     }
+
+    #[test]
+    fn decode_gf2_5() {
+        let data = [17, 19, 11, 1, 14, 10, 21, 0, 23, 17, 31, 18];
+        let ecc = 8;
+
+        let encoded = Encoder::<gf::GF2_5>::new(ecc).encode(&data[..]);
+        let mut errd = *encoded;
+
+        errd[0] ^= 1;
+        errd[5] ^= 7;
+        errd[11] ^= 30;
+        errd[19] ^= 12;
+
+        let decoder = Decoder::<gf::GF2_5>::new(ecc);
+        let (decoded, err) = decoder.correct_err_count(&errd, None).unwrap();
+
+        assert_eq!(data, decoded.data());
+        assert_eq!(err, 4);
+    }
 }